@@ -0,0 +1,355 @@
+use chrono::SecondsFormat;
+use crate::Config;
+use crate::frontmatter::{self, Post};
+use pulldown_cmark::{html, Options, Parser};
+use rss::{Category, Channel, Guid, Item};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A minimal static site generator for when you don't want to install one
+// Used when ssg_type = "builtin" and no 'process' command is set
+//
+// Templates are plain HTML files in template_dir with {{ placeholders }}:
+//   post.html - a single post
+//   list.html - the home page and tag pages, {{ posts }} is a list of item.html
+//   item.html - one post in a list
+// Anything in template_dir/static is copied to the output directory as-is
+// Any template that doesn't exist falls back to the defaults below
+
+const POST_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }} | {{ site_title }}</title>
+<meta name="description" content="{{ summary }}">
+<link rel="alternate" type="application/rss+xml" href="{{ feed_url }}">
+</head>
+<body>
+<header><a href="/">{{ site_title }}</a></header>
+<main>
+<article>
+<h1>{{ title }}</h1>
+<p>{{ subtitle }}</p>
+<p><time datetime="{{ datetime }}">{{ date }}</time> {{ author }}</p>
+{{ content }}
+<p>{{ tags }}</p>
+</article>
+</main>
+</body>
+</html>
+"#;
+
+const LIST_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ title }}</title>
+<link rel="alternate" type="application/rss+xml" href="{{ feed_url }}">
+</head>
+<body>
+<header><a href="/">{{ site_title }}</a></header>
+<main>
+<h1>{{ title }}</h1>
+<ul>
+{{ posts }}
+</ul>
+</main>
+</body>
+</html>
+"#;
+
+const ITEM_TEMPLATE: &str = r#"<li><a href="{{ url }}">{{ title }}</a> <time datetime="{{ datetime }}">{{ date }}</time><br>{{ summary }}</li>"#;
+
+struct Templates {
+  post: String,
+  list: String,
+  item: String
+}
+
+fn load_template(dir: &Path, name: &str, default: &str) -> io::Result<String> {
+  let path = dir.join(name);
+  if path.is_file() {
+    fs::read_to_string(path)
+  } else {
+    Ok(default.to_string())
+  }
+}
+
+// replace {{ key }} (or {{key}}) with the value, in one pass so that
+// anything that looks like a placeholder inside a value is left alone
+pub fn fill(template: &str, values: &[(&str, String)]) -> String {
+  let mut out = String::with_capacity(template.len());
+  let mut rest = template;
+  while let Some(start) = rest.find("{{") {
+    out.push_str(&rest[..start]);
+    let after = &rest[start + 2..];
+    let found = after.find("}}").and_then(|end| {
+      let key = after[..end].trim();
+      values.iter().find(|(k, _)| *k == key).map(|(_, v)| (v, end))
+    });
+    match found {
+      Some((value, end)) => {
+        out.push_str(value);
+        rest = &after[end + 2..];
+      },
+      None => {
+        out.push_str("{{");
+        rest = after;
+      }
+    }
+  }
+  out.push_str(rest);
+  out
+}
+
+pub fn escape(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&#39;")
+}
+
+pub fn slugify(s: &str) -> String {
+  let mut cloned = s.trim().to_lowercase();
+  cloned.retain(|c| c.is_alphanumeric() || c == ' ' || c == '-');
+  itertools::join(cloned.split_whitespace(), "-")
+}
+
+// copy every file that isn't markdown, keeping the same relative path
+// 'skip' stops us copying the output directory into itself if it lives inside the input directory
+fn copy_static(from: &Path, to: &Path, skip: &Path, written: &mut Vec<PathBuf>) -> io::Result<()> {
+  for entry in fs::read_dir(from)? {
+    let path = entry?.path();
+    let name = path.file_name().unwrap_or_default();
+    if name.to_string_lossy().starts_with('.') || path == skip {
+      continue
+    }
+    let target = to.join(name);
+    if path.is_dir() {
+      copy_static(&path, &target, skip, written)?;
+    } else if !frontmatter::is_markdown(&path) {
+      fs::create_dir_all(to)?;
+      fs::copy(&path, &target)?;
+      written.push(target);
+    }
+  }
+  Ok(())
+}
+
+fn write(path: &Path, contents: String, written: &mut Vec<PathBuf>) -> io::Result<()> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+  fs::write(path, contents)?;
+  written.push(path.to_path_buf());
+  Ok(())
+}
+
+// Every file the last build wrote is listed in ~/.letters_built, one per line
+// Anything we wrote last time but not this time (e.g. a deleted post) is removed from output,
+// along with any directories that leaves empty. Files we didn't write are never touched
+fn clean(output: &Path, written: &[PathBuf]) -> io::Result<()> {
+  let list = PathBuf::from(shellexpand::full("~/.letters_built").expect("Error reading home directory").as_ref());
+  let previous = fs::read_to_string(&list).unwrap_or_default();
+  for old in previous.lines().map(PathBuf::from) {
+    if !old.starts_with(output) || written.contains(&old) || !old.is_file() {
+      continue
+    }
+    fs::remove_file(&old)?;
+    let mut dir = old.parent();
+    while let Some(d) = dir {
+      if d == output || fs::remove_dir(d).is_err() {
+        break
+      }
+      dir = d.parent();
+    }
+  }
+  let lines: Vec<String> = written.iter().map(|p| p.to_string_lossy().to_string()).collect();
+  fs::write(list, lines.join("\n"))
+}
+
+struct Page<'a> {
+  post: &'a Post,
+  url: String,
+  values: Vec<(&'static str, String)>
+}
+
+pub fn build(config: &Config) -> io::Result<bool> {
+
+  let input = PathBuf::from(shellexpand::full(&config.input).expect("Error reading input directory").as_ref());
+  let output = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let wd = PathBuf::from(shellexpand::full(&config.workdir).expect("Error reading working directory").as_ref());
+  let template_dir = wd.join(shellexpand::full(&config.template_dir).expect("Error reading template directory").as_ref());
+
+  let templates = Templates {
+    post: load_template(&template_dir, "post.html", POST_TEMPLATE)?,
+    list: load_template(&template_dir, "list.html", LIST_TEMPLATE)?,
+    item: load_template(&template_dir, "item.html", ITEM_TEMPLATE)?
+  };
+
+  let site_url = config.site_url.trim_end_matches('/');
+  let site_title = if config.site_title.is_empty() { config.author.as_str() } else { config.site_title.as_str() };
+
+  // the feed goes wherever rss_file says, as long as that is inside the output directory
+  let rss_file = if config.rss_file.is_empty() {
+    output.join("rss.xml")
+  } else {
+    PathBuf::from(shellexpand::full(&config.rss_file).expect("Error reading rss filepath").as_ref())
+  };
+  let feed_url = match rss_file.strip_prefix(&output) {
+    Ok(p) => ["/", &p.to_string_lossy()].concat(),
+    Err(_) => String::from("/rss.xml")
+  };
+
+  // read and check all the posts before writing anything
+  let mut posts = Vec::new();
+  for post in frontmatter::read_all(&input)? {
+    if post.front.draft {
+      continue
+    }
+    if post.front.date().is_none() {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: missing or invalid date", post.path.display())))
+    }
+    if let Some(other) = posts.iter().find(|p: &&Post| p.slug == post.slug) {
+      return Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+        "{} and {} would both be published at /{}/, rename one of them", other.path.display(), post.path.display(), post.slug
+      )))
+    }
+    posts.push(post);
+  }
+  // newest first
+  posts.sort_by_key(|p| std::cmp::Reverse(p.front.date()));
+
+  let mut pages = Vec::new();
+  for post in &posts {
+    let date = post.front.date().unwrap();
+    let url = ["/", &post.slug, "/"].concat();
+    let tags = post.front.tags.iter()
+      .filter(|t| !t.trim().is_empty())
+      .map(|t| format!("<a href=\"/tags/{}/\">{}</a>", slugify(t), escape(t.trim())));
+    let (photo, description) = match &post.front.image {
+      Some(i) => (i.photo.clone(), i.description.clone()),
+      None => (post.front.images.first().cloned().unwrap_or_default(), String::new())
+    };
+    let values = vec![
+      ("site_title", escape(site_title)),
+      ("site_url", escape(site_url)),
+      ("feed_url", escape(&feed_url)),
+      ("url", escape(&url)),
+      ("title", escape(post.front.title.trim())),
+      ("subtitle", escape(post.front.subtitle.trim())),
+      ("summary", escape(post.front.summary.trim())),
+      ("author", escape(if post.front.author.is_empty() { &config.author } else { &post.front.author })),
+      ("date", date.format("%-d %B %Y").to_string()),
+      ("datetime", date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+      ("image", escape(&photo)),
+      ("image_description", escape(&description)),
+      ("tags", itertools::join(tags, ", "))
+    ];
+    pages.push(Page { post, url, values });
+  }
+
+  let mut written = Vec::new();
+
+  // posts
+  for page in &pages {
+    let mut content = String::new();
+    html::push_html(&mut content, Parser::new_ext(&page.post.body, Options::all()));
+    let mut values = page.values.clone();
+    values.push(("content", content));
+    write(&output.join(&page.post.slug).join("index.html"), fill(&templates.post, &values), &mut written)?;
+  }
+
+  let list = |title: &str, pages: &[&Page]| -> String {
+    let items = pages.iter().map(|p| fill(&templates.item, &p.values));
+    fill(&templates.list, &[
+      ("site_title", escape(site_title)),
+      ("site_url", escape(site_url)),
+      ("feed_url", escape(&feed_url)),
+      ("title", escape(title)),
+      ("posts", itertools::join(items, "\n"))
+    ])
+  };
+
+  // home page
+  let all: Vec<&Page> = pages.iter().collect();
+  write(&output.join("index.html"), list(site_title, &all), &mut written)?;
+
+  // tag pages
+  let mut tagged: BTreeMap<String, (String, Vec<&Page>)> = BTreeMap::new();
+  for page in &pages {
+    for tag in page.post.front.tags.iter().filter(|t| !t.trim().is_empty()) {
+      tagged.entry(slugify(tag))
+        .or_insert_with(|| (tag.trim().to_string(), Vec::new()))
+        .1.push(page);
+    }
+  }
+  for (slug, (name, tag_pages)) in &tagged {
+    write(&output.join("tags").join(slug).join("index.html"), list(&[site_title, ": ", name].concat(), tag_pages), &mut written)?;
+  }
+
+  // RSS feed
+  // lette.rs treats the last item as the newest when tooting, so the feed is in date order
+  let items: Vec<Item> = pages.iter().rev().map(|page| {
+    let front = &page.post.front;
+    let link = [site_url, &page.url].concat();
+    let mut item = Item::default();
+    item.set_title(front.title.trim().to_string());
+    item.set_link(link.clone());
+    item.set_description(front.summary.trim().to_string());
+    item.set_pub_date(front.date().unwrap().to_rfc2822());
+    let mut guid = Guid::default();
+    guid.set_value(link);
+    guid.set_permalink(true);
+    item.set_guid(guid);
+    item.set_categories(front.tags.iter().map(|t| {
+      let mut c = Category::default();
+      c.set_name(t.trim().to_string());
+      c
+    }).collect::<Vec<Category>>());
+    item
+  }).collect();
+  let mut channel = Channel::default();
+  channel.set_title(site_title.to_string());
+  channel.set_link([site_url, "/"].concat());
+  channel.set_description(site_title.to_string());
+  channel.set_items(items);
+  write(&rss_file, channel.to_string(), &mut written)?;
+
+  // static files from the template directory, then anything else in the input directory
+  let static_dir = template_dir.join("static");
+  if static_dir.is_dir() {
+    copy_static(&static_dir, &output, &output, &mut written)?;
+  }
+  copy_static(&input, &output, &output, &mut written)?;
+
+  clean(&output, &written)?;
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn fills_placeholders_with_or_without_spaces() {
+    let values = [("title", String::from("Hello")), ("author", String::from("Ann"))];
+    assert_eq!(fill("<h1>{{ title }}</h1> by {{author}}", &values), "<h1>Hello</h1> by Ann");
+  }
+
+  #[test]
+  fn leaves_unknown_placeholders() {
+    assert_eq!(fill("{{ nope }} {{", &[("title", String::from("x"))]), "{{ nope }} {{");
+  }
+
+  #[test]
+  fn does_not_fill_placeholders_inside_values() {
+    let values = [("summary", String::from("about {{ title }}")), ("title", String::from("Hello"))];
+    assert_eq!(fill("{{ summary }} - {{ title }}", &values), "about {{ title }} - Hello");
+  }
+}
@@ -0,0 +1,59 @@
+use colol::color;
+use crate::{setup, Config};
+use serde_derive::{Deserialize, Serialize};
+use std::io::{self, IsTerminal};
+
+// What publishing will do to the files on the server, by path
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Changes {
+  pub added: Vec<String>,
+  pub updated: Vec<String>,
+  pub deleted: Vec<String>
+}
+
+impl Changes {
+  pub fn is_empty(&self) -> bool {
+    self.added.is_empty() && self.updated.is_empty() && self.deleted.is_empty()
+  }
+}
+
+pub fn show(changes: &Changes) {
+  for (symbol, list) in [("+", &changes.added), ("~", &changes.updated), ("-", &changes.deleted)].iter() {
+    for path in list.iter() {
+      match *symbol {
+        "+" => color!(green),
+        "-" => color!(red),
+        _ => color!(yellow)
+      }
+      print!("{} ", symbol);
+      color!(reset);
+      println!("{}", path);
+    }
+  }
+}
+
+pub fn summary(changes: &Changes) -> String {
+  format!("{} new, {} changed, {} deleted", changes.added.len(), changes.updated.len(), changes.deleted.len())
+}
+
+// ask before deleting lots of files from the server, in case output is in the wrong place
+// --yes skips the question, and without a terminal to ask in (e.g. cron or CI) the answer is no
+pub fn confirm(config: &Config, changes: &Changes, destination: &str) -> bool {
+  let deleting = changes.deleted.len();
+  if deleting <= config.confirm_deletions {
+    return true
+  }
+  if config.yes {
+    println!("🗑  Deleting {} files from {}", deleting, destination);
+    return true
+  }
+  show(&Changes { deleted: changes.deleted.clone(), ..Default::default() });
+  if !io::stdin().is_terminal() {
+    eprintln!("😬 Publishing would delete these {} files from {}, and there's no terminal to ask you about it.", deleting, destination);
+    eprintln!("Use --yes to publish anyway, or raise confirm_deletions in your config file.");
+    return false
+  }
+  let question = format!("Publishing will delete these {} files from {}. Continue?", deleting, destination);
+  setup::ask_yes_no(&question, false)
+}
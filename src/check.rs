@@ -0,0 +1,450 @@
+use colol::color;
+use crate::serve::{self, Resolved};
+use crate::{snapshot, Config};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Checks the links in the processed site before it is published
+// Internal links are checked the same way 'letters test' serves pages,
+// so /about/ needs about/index.html and /about can also be about.html
+
+// external links that worked are not checked again for this long
+const CACHE_DAYS: u64 = 7;
+const ATTEMPTS: u64 = 3;
+const WORKERS: usize = 8;
+
+struct Tag {
+  name: String,
+  attrs: Vec<(String, String)>
+}
+
+impl Tag {
+  fn attr(&self, name: &str) -> Option<&str> {
+    self.attrs.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+  }
+}
+
+#[derive(Default)]
+struct Page {
+  links: Vec<String>,
+  ids: HashSet<String>
+}
+
+#[derive(Debug, PartialEq)]
+enum Link {
+  // a path from the root of the site, and the part after the #
+  Internal(String, String),
+  External(String),
+  Skip
+}
+
+// the end of a tag, ignoring any > inside quoted attributes
+fn tag_end(s: &str) -> Option<usize> {
+  let mut quote = None;
+  for (i, c) in s.char_indices() {
+    match (quote, c) {
+      (None, '"') | (None, '\'') => quote = Some(c),
+      (Some(q), _) if c == q => quote = None,
+      (None, '>') => return Some(i),
+      _ => ()
+    }
+  }
+  None
+}
+
+fn unescape(s: &str) -> String {
+  s.replace("&quot;", "\"")
+    .replace("&#39;", "'")
+    .replace("&#x27;", "'")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&amp;", "&")
+}
+
+fn parse_tag(s: &str) -> Option<Tag> {
+  let name: String = s.chars().take_while(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+  if name.is_empty() {
+    // closing tags, doctype etc
+    return None
+  }
+  let mut attrs = Vec::new();
+  let mut rest = s[name.len()..].trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+  while !rest.is_empty() {
+    let end = rest.find(|c: char| c.is_whitespace() || c == '=' || c == '/').unwrap_or(rest.len());
+    let attr = rest[..end].to_lowercase();
+    rest = rest[end..].trim_start();
+    let mut value = String::new();
+    if let Some(r) = rest.strip_prefix('=') {
+      let r = r.trim_start();
+      let (v, after) = match r.chars().next() {
+        Some(q) if q == '"' || q == '\'' => {
+          let close = r[1..].find(q).map(|i| i + 1).unwrap_or(r.len());
+          (&r[1..close], r.get(close + 1..).unwrap_or(""))
+        },
+        _ => {
+          let close = r.find(char::is_whitespace).unwrap_or(r.len());
+          (&r[..close], &r[close..])
+        }
+      };
+      value = unescape(v);
+      rest = after;
+    }
+    if !attr.is_empty() {
+      attrs.push((attr, value));
+    }
+    rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+  }
+  Some(Tag { name, attrs })
+}
+
+// every opening tag in a page, skipping comments and the insides of scripts and styles
+fn tags(html: &str) -> Vec<Tag> {
+  let mut found = Vec::new();
+  let mut rest = html;
+  while let Some(start) = rest.find('<') {
+    rest = &rest[start + 1..];
+    if let Some(r) = rest.strip_prefix("!--") {
+      rest = r.find("-->").map(|e| &r[e + 3..]).unwrap_or("");
+      continue
+    }
+    let end = match tag_end(rest) {
+      Some(e) => e,
+      None => break
+    };
+    let tag = parse_tag(&rest[..end]);
+    rest = &rest[end + 1..];
+    if let Some(tag) = tag {
+      if tag.name == "script" || tag.name == "style" {
+        let close = ["</", &tag.name].concat();
+        rest = rest.to_ascii_lowercase().find(&close).map(|i| &rest[i..]).unwrap_or("");
+      }
+      found.push(tag);
+    }
+  }
+  found
+}
+
+fn read_page(path: &Path) -> io::Result<Page> {
+  let html = String::from_utf8_lossy(&fs::read(path)?).to_string();
+  let mut page = Page::default();
+  for tag in tags(&html) {
+    if let Some(id) = tag.attr("id") {
+      page.ids.insert(id.to_string());
+    }
+    if tag.name == "a" {
+      if let Some(name) = tag.attr("name") {
+        page.ids.insert(name.to_string());
+      }
+    }
+    // these only point at a server, not a page
+    let rel = tag.attr("rel").unwrap_or("");
+    if tag.name == "link" && (rel.contains("preconnect") || rel.contains("dns-prefetch")) {
+      continue
+    }
+    for attr in ["href", "src", "poster"].iter() {
+      if let Some(link) = tag.attr(attr) {
+        page.links.push(link.to_string());
+      }
+    }
+    // "small.jpg 480w, large.jpg 1080w"
+    if let Some(srcset) = tag.attr("srcset") {
+      page.links.extend(srcset.split(',').filter_map(|s| s.split_whitespace().next()).map(String::from));
+    }
+  }
+  Ok(page)
+}
+
+// remove . and .. from a path like /posts/one/../two/
+fn normalise(path: &str) -> String {
+  let mut parts: Vec<&str> = Vec::new();
+  for part in path.split('/') {
+    match part {
+      "" | "." => (),
+      ".." => { parts.pop(); },
+      p => parts.push(p)
+    }
+  }
+  let last = path.rsplit('/').next().unwrap_or("");
+  let trailing = if parts.is_empty() || last.is_empty() || last == "." || last == ".." { "/" } else { "" };
+  let joined = ["/", &parts.join("/")].concat();
+  if joined == "/" { joined } else { [joined.as_str(), trailing].concat() }
+}
+
+fn has_scheme(link: &str) -> bool {
+  match link.find(':') {
+    Some(i) => {
+      let scheme = &link[..i];
+      !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    },
+    None => false
+  }
+}
+
+// 'page' is the path of the page the link is on, relative to the output directory
+fn classify(link: &str, page: &str, site_url: &str) -> Link {
+  let link = link.trim();
+  let site_url = site_url.trim_end_matches('/');
+  // if the site lives at https://example.com/blog then /blog/about/ is about/ in the output directory
+  let prefix = site_url.splitn(4, '/').nth(3).map(|p| ["/", p].concat()).unwrap_or_default();
+
+  let path = if link.is_empty() {
+    return Link::Skip
+  } else if link.starts_with('#') {
+    ["/", page, link].concat()
+  } else if !site_url.is_empty() && (link == site_url || link.starts_with(&[site_url, "/"].concat())) {
+    [prefix.as_str(), &link[site_url.len()..]].concat()
+  } else if link.starts_with("//") {
+    return Link::External(["https:", link].concat())
+  } else if link.starts_with("http://") || link.starts_with("https://") {
+    return Link::External(link.to_string())
+  } else if has_scheme(link) {
+    // mailto:, tel:, data: etc
+    return Link::Skip
+  } else if link.starts_with('/') {
+    link.to_string()
+  } else {
+    let dir = page.rsplit_once('/').map(|(d, _)| d).unwrap_or("");
+    ["/", dir, "/", link].concat()
+  };
+
+  let (path, fragment) = path.split_once('#').unwrap_or((&path, ""));
+  let path = path.split('?').next().unwrap_or(path);
+  let path = if !prefix.is_empty() && (path == prefix || path.starts_with(&[prefix.as_str(), "/"].concat())) {
+    &path[prefix.len()..]
+  } else {
+    path
+  };
+  Link::Internal(normalise(path), serve::decode(fragment))
+}
+
+// the file a path refers to, following redirects for directories
+fn target(root: &Path, path: &str) -> Option<PathBuf> {
+  match serve::resolve(root, path) {
+    Resolved::File(file) => Some(file),
+    Resolved::Redirect(location) => match serve::resolve(root, &location) {
+      Resolved::File(file) => Some(file),
+      _ => None
+    },
+    Resolved::Missing => None
+  }
+}
+
+fn cache_file() -> PathBuf {
+  PathBuf::from(shellexpand::tilde("~/.letters_links").as_ref())
+}
+
+fn now() -> u64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+// one "checked at<TAB>url" per line, only for links that worked
+fn load_cache() -> HashMap<String, u64> {
+  let oldest = now().saturating_sub(CACHE_DAYS * 24 * 60 * 60);
+  fs::read_to_string(cache_file())
+    .unwrap_or_default()
+    .lines()
+    .filter_map(|l| l.split_once('\t'))
+    .filter_map(|(time, url)| time.parse::<u64>().ok().map(|t| (url.to_string(), t)))
+    .filter(|(_, t)| *t >= oldest)
+    .collect()
+}
+
+fn save_cache(cache: &HashMap<String, u64>) -> io::Result<()> {
+  let mut lines: Vec<String> = cache.iter()
+    .map(|(url, time)| [time.to_string(), "\t".to_string(), url.to_string()].concat())
+    .collect();
+  lines.sort();
+  fs::write(cache_file(), lines.join("\n"))
+}
+
+// retry errors and responses that might be temporary
+fn check_url(client: &reqwest::blocking::Client, url: &str) -> Result<(), String> {
+  let mut problem = String::new();
+  for attempt in 0..ATTEMPTS {
+    if attempt > 0 {
+      thread::sleep(Duration::from_secs(attempt));
+    }
+    // some servers don't like HEAD, so try GET before giving up
+    let response = match client.head(url).send() {
+      Ok(r) if r.status().is_success() => Ok(r),
+      _ => client.get(url).send()
+    };
+    match response {
+      Ok(r) if r.status().is_success() => return Ok(()),
+      Ok(r) if r.status().is_server_error() || r.status().as_u16() == 429 => problem = r.status().to_string(),
+      Ok(r) => return Err(r.status().to_string()),
+      Err(e) => problem = e.to_string()
+    }
+  }
+  Err(problem)
+}
+
+// returns the links that didn't work, and why
+fn check_external(urls: Vec<&String>) -> BTreeMap<String, String> {
+  let mut cache = load_cache();
+  let queue: Vec<&String> = urls.into_iter().filter(|u| !cache.contains_key(*u)).collect();
+  let broken = Mutex::new(BTreeMap::new());
+  if queue.is_empty() {
+    return BTreeMap::new()
+  }
+  println!("Checking {} external links...", queue.len());
+  let client = match reqwest::blocking::Client::builder()
+    .timeout(Duration::from_secs(15))
+    .user_agent("lette.rs link checker")
+    .build() {
+      Ok(c) => c,
+      Err(e) => {
+        eprintln!("😬 couldn't check external links: {}", e);
+        return BTreeMap::new()
+      }
+    };
+  let checked = Mutex::new(Vec::new());
+  let queue = Mutex::new(queue);
+  thread::scope(|s| {
+    for _ in 0..WORKERS {
+      s.spawn(|| loop {
+        let url = match queue.lock().unwrap().pop() {
+          Some(u) => u,
+          None => break
+        };
+        match check_url(&client, url) {
+          Ok(()) => checked.lock().unwrap().push(url.to_string()),
+          Err(e) => { broken.lock().unwrap().insert(url.to_string(), e); }
+        }
+      });
+    }
+  });
+  let time = now();
+  cache.extend(checked.into_inner().unwrap().into_iter().map(|url| (url, time)));
+  if let Err(e) = save_cache(&cache) {
+    eprintln!("😬 couldn't save the external link cache: {}", e);
+  }
+  broken.into_inner().unwrap()
+}
+
+pub fn check(config: &Config) -> io::Result<bool> {
+  let root = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let mut files = HashMap::new();
+  snapshot(&root, &mut files);
+  let mut html: Vec<PathBuf> = files.into_keys()
+    .filter(|p| matches!(p.extension().and_then(|e| e.to_str()), Some("html") | Some("htm")))
+    .collect();
+  html.sort();
+  if html.is_empty() {
+    eprintln!("😭 There are no pages in {} to check. Run 'letters process' first.", root.display());
+    return Ok(false)
+  }
+
+  let mut pages = HashMap::new();
+  for path in &html {
+    pages.insert(path.clone(), read_page(path)?);
+  }
+
+  // problems for each page, by the page's path in the output directory
+  let mut problems: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+  let mut external: BTreeMap<String, Vec<String>> = BTreeMap::new();
+  let mut count = 0;
+  for path in &html {
+    let name = path.strip_prefix(&root).unwrap_or(path).to_string_lossy().to_string();
+    for link in &pages[path].links {
+      match classify(link, &name, &config.site_url) {
+        Link::Internal(target_path, fragment) => {
+          count += 1;
+          let problem = match target(&root, &target_path) {
+            None => Some(["missing: ", link].concat()),
+            // #top and # always work
+            Some(file) if !fragment.is_empty() && fragment != "top" => match pages.get(&file) {
+              Some(page) if !page.ids.contains(&fragment) => Some(["missing anchor: ", link].concat()),
+              _ => None
+            },
+            _ => None
+          };
+          if let Some(p) = problem {
+            problems.entry(name.clone()).or_default().insert(p);
+          }
+        },
+        Link::External(url) => if config.check_external {
+          count += 1;
+          external.entry(url).or_default().push(name.clone());
+        },
+        Link::Skip => ()
+      }
+    }
+  }
+
+  if config.check_external {
+    for (url, reason) in check_external(external.keys().collect()) {
+      for page in &external[&url] {
+        problems.entry(page.clone()).or_default().insert(format!("broken: {} ({})", url, reason));
+      }
+    }
+  }
+
+  if problems.is_empty() {
+    println!("✅ Checked {} links on {} pages, everything works", count, html.len());
+    return Ok(true)
+  }
+  let broken: usize = problems.values().map(|p| p.len()).sum();
+  for (page, list) in &problems {
+    color!(yellow);
+    println!("{}", page);
+    color!(reset);
+    for problem in list {
+      println!("  {}", problem);
+    }
+  }
+  eprintln!("😭 Found {} broken links on {} of {} pages", broken, problems.len(), html.len());
+  Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn internal(path: &str, fragment: &str) -> Link {
+    Link::Internal(path.to_string(), fragment.to_string())
+  }
+
+  #[test]
+  fn normalises_paths() {
+    assert_eq!(normalise("/posts/one/../two/"), "/posts/two/");
+    assert_eq!(normalise("/posts/./one.html"), "/posts/one.html");
+    assert_eq!(normalise("/posts/one/.."), "/posts/");
+    assert_eq!(normalise("/../../"), "/");
+    assert_eq!(normalise("//a//b"), "/a/b");
+    assert_eq!(normalise(""), "/");
+  }
+
+  #[test]
+  fn classifies_relative_and_root_links() {
+    assert_eq!(classify("two/", "posts/one/index.html", ""), internal("/posts/one/two/", ""));
+    assert_eq!(classify("../img/a.png", "posts/one/index.html", ""), internal("/posts/img/a.png", ""));
+    assert_eq!(classify("/about/?x=1#team", "index.html", ""), internal("/about/", "team"));
+    assert_eq!(classify("#top", "posts/one/index.html", ""), internal("/posts/one/index.html", "top"));
+    assert_eq!(classify("a%20b/#caf%C3%A9", "index.html", ""), internal("/a%20b/", "café"));
+  }
+
+  #[test]
+  fn links_to_our_own_site_are_internal() {
+    assert_eq!(classify("https://example.com/about/", "index.html", "https://example.com/"), internal("/about/", ""));
+    assert_eq!(classify("https://example.com", "index.html", "https://example.com"), internal("/", ""));
+    // a site that lives in a subdirectory
+    assert_eq!(classify("https://example.com/blog/about/", "index.html", "https://example.com/blog"), internal("/about/", ""));
+    assert_eq!(classify("/blog/about/", "index.html", "https://example.com/blog"), internal("/about/", ""));
+    assert_eq!(classify("/other/", "index.html", "https://example.com/blog"), internal("/other/", ""));
+  }
+
+  #[test]
+  fn classifies_external_and_other_links() {
+    assert_eq!(classify("https://example.org/x", "index.html", "https://example.com"), Link::External(String::from("https://example.org/x")));
+    assert_eq!(classify("//example.org/x", "index.html", ""), Link::External(String::from("https://example.org/x")));
+    assert_eq!(classify("mailto:me@example.com", "index.html", ""), Link::Skip);
+    assert_eq!(classify("data:image/png;base64,AAAA", "index.html", ""), Link::Skip);
+    assert_eq!(classify("  ", "index.html", ""), Link::Skip);
+  }
+}
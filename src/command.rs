@@ -0,0 +1,140 @@
+use crate::Config;
+use std::io;
+use subprocess::Exec;
+
+// Commands from the config file are split into words the same way a shell would,
+// so quoted arguments like --input="my posts" stay together
+// Leading NAME=value words are environment variables, like in a shell
+// Commands that use anything else the shell understands (pipes, redirects, &&, $VARIABLES, globs etc)
+// are run with the shell instead, so they work exactly as they would in a terminal
+// Either way, everything in the [env] table is passed to the command
+
+// looked for anywhere in the command, including inside words e.g. 2>/dev/null or *.md
+const SHELL_CHARACTERS: &str = "|&;<>()$`*?[";
+
+fn needs_shell(cmd: &str, words: &[String]) -> bool {
+  // we only expand ~ at the start of a word
+  cmd.contains(|c| SHELL_CHARACTERS.contains(c)) || words.iter().any(|w| w.chars().skip(1).any(|c| c == '~'))
+}
+
+fn assignment(word: &str) -> Option<(String, String)> {
+  let (name, value) = word.split_once('=')?;
+  let mut chars = name.chars();
+  let valid = chars.next().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+    && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+  if valid {
+    Some((name.to_string(), value.to_string()))
+  } else {
+    None
+  }
+}
+
+pub struct Parsed {
+  pub argv: Vec<String>,
+  // [env] first, then any NAME=value from the command itself
+  pub env: Vec<(String, String)>
+}
+
+// 'args' are added to the end of the command, e.g. the source and destination for rsync
+pub fn parse(cmd: &str, args: &[String], config: &Config) -> io::Result<Parsed> {
+  let words = shell_words::split(cmd).map_err(|e| io::Error::new(
+    io::ErrorKind::InvalidInput,
+    format!("couldn't read the command '{}': {}", cmd, e)
+  ))?;
+  let mut env: Vec<(String, String)> = config.env.iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+
+  if needs_shell(cmd, &words) {
+    let quoted = itertools::join(args.iter().map(|a| shell_words::quote(a)), " ");
+    let full = if quoted.is_empty() { cmd.to_string() } else { [cmd, " ", &quoted].concat() };
+    let argv = vec![String::from("sh"), String::from("-c"), full];
+    return Ok(Parsed { argv, env })
+  }
+
+  let mut argv = Vec::new();
+  for word in words {
+    // expand ~ the way the shell would
+    let word = shellexpand::tilde(&word).to_string();
+    match assignment(&word) {
+      Some(var) if argv.is_empty() => env.push(var),
+      _ => argv.push(word)
+    }
+  }
+  if argv.is_empty() {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("there is no command to run in '{}'", cmd)))
+  }
+  argv.extend(args.iter().cloned());
+  Ok(Parsed { argv, env })
+}
+
+pub fn build(cmd: &str, args: &[String], config: &Config) -> io::Result<Exec> {
+  let parsed = parse(cmd, args, config)?;
+  Ok(Exec::cmd(&parsed.argv[0])
+    .args(&parsed.argv[1..])
+    .env_extend(&parsed.env))
+}
+
+// always run with the shell, for commands that are expected to use $VARIABLES
+pub fn shell(cmd: &str, config: &Config) -> Exec {
+  Exec::cmd("sh")
+    .arg("-c")
+    .arg(cmd)
+    .env_extend(&config.env.iter().collect::<Vec<_>>())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config(env: &str) -> Config {
+    let toml = ["author = \"\"\ninput = \"\"\noutput = \"\"\nworkdir = \"\"\n[commands]\n[social]\n[env]\n", env].concat();
+    toml::from_str(&toml).unwrap()
+  }
+
+  fn argv(cmd: &str, args: &[&str]) -> Vec<String> {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    parse(cmd, &args, &config("")).unwrap().argv
+  }
+
+  #[test]
+  fn splits_words_like_a_shell() {
+    assert_eq!(argv("eleventy --input=\"my posts\" 'a b'", &[]), ["eleventy", "--input=my posts", "a b"]);
+  }
+
+  #[test]
+  fn adds_args_to_the_end() {
+    assert_eq!(argv("rsync -rtO", &["out/", "host:/var/www"]), ["rsync", "-rtO", "out/", "host:/var/www"]);
+  }
+
+  #[test]
+  fn leading_assignments_are_environment_variables() {
+    let parsed = parse("NODE_ENV=production npx eleventy A=b", &[], &config("DEBUG = \"1\"")).unwrap();
+    assert_eq!(parsed.argv, ["npx", "eleventy", "A=b"]);
+    assert_eq!(parsed.env, [(String::from("DEBUG"), String::from("1")), (String::from("NODE_ENV"), String::from("production"))]);
+  }
+
+  #[test]
+  fn expands_tilde_at_the_start_of_words() {
+    let home = shellexpand::tilde("~").to_string();
+    assert_eq!(argv("hugo -s ~/blog", &[]), vec![String::from("hugo"), String::from("-s"), [home.as_str(), "/blog"].concat()]);
+  }
+
+  #[test]
+  fn uses_the_shell_when_it_has_to() {
+    for cmd in [
+      "hugo | tee log", "hugo && echo done", "hugo&&echo done", "hugo 2>/dev/null", "hugo >out.log",
+      "echo $HOME", "echo ${HOME}", "ls *.md", "ls post?.md", "ls [ab].md", "echo `date`", "echo $(date)",
+      "hugo; echo done", "hugo -d=~/public"
+    ] {
+      assert_eq!(argv(cmd, &["x y"]), ["sh", "-c", &[cmd, " 'x y'"].concat()], "{}", cmd);
+    }
+  }
+
+  #[test]
+  fn rejects_empty_and_unbalanced_commands() {
+    assert!(parse("", &[], &config("")).is_err());
+    assert!(parse("A=b", &[], &config("")).is_err());
+    assert!(parse("hugo \"oops", &[], &config("")).is_err());
+  }
+}
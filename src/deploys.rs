@@ -0,0 +1,211 @@
+use crate::changes::{self, Changes};
+use crate::{newest_post, releases, Config};
+use chrono::{DateTime, Local, SecondsFormat, Utc};
+use serde_derive::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use subprocess::{Exec, ExitStatus, NullFile, Redirection};
+
+// A history of everything we've published, one JSON object per line in ~/.letters_deploys
+// Deploys are numbered from 1, oldest first, so the numbers never change
+
+// whether this run recorded a deploy, so social posts aren't added to one from before
+static RECORDED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Deserialize)]
+pub struct Post {
+  pub title: String,
+  pub link: String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SocialPost {
+  pub platform: String,
+  pub url: String
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Deploy {
+  pub time: String,
+  pub site: String,
+  pub method: String,
+  pub target: String,
+  // which of [[targets]] it was, if any
+  #[serde(default)]
+  pub target_name: Option<String>,
+  #[serde(default)]
+  pub release: Option<String>,
+  // the commit your workdir was at, if it's a git repository
+  #[serde(default)]
+  pub commit: Option<String>,
+  #[serde(default)]
+  pub uncommitted: bool,
+  // None if we couldn't tell what changed, e.g. with a publish command that doesn't use rsync
+  #[serde(default)]
+  pub changes: Option<Changes>,
+  #[serde(default)]
+  pub newest_post: Option<Post>,
+  #[serde(default)]
+  pub social: Vec<SocialPost>
+}
+
+fn history_file() -> PathBuf {
+  PathBuf::from(shellexpand::tilde("~/.letters_deploys").as_ref())
+}
+
+fn git(workdir: &str, args: &[&str]) -> Option<String> {
+  let capture = Exec::cmd("git")
+    .arg("-C")
+    .arg(workdir)
+    .args(args)
+    .stdout(Redirection::Pipe)
+    .stderr(NullFile)
+    .capture()
+    .ok()?;
+  if capture.exit_status == ExitStatus::Exited(0) {
+    Some(capture.stdout_str().trim().to_string())
+  } else {
+    None
+  }
+}
+
+fn target(config: &Config) -> String {
+  match config.publish_method.as_str() {
+    "s3" => ["s3://", &config.s3.bucket, "/", config.s3.prefix.trim_matches('/')].concat(),
+    "git" => [&config.git.repo, " ", &config.git.branch].concat(),
+    _ if config.atomic_releases => [&config.server_name, ":", &releases::remote_dir(config)].concat(),
+    _ => [&config.server_name, ":", shellexpand::full(&config.remote_dir).expect("Error reading remote directory").as_ref()].concat()
+  }
+}
+
+impl Deploy {
+  // everything we know before publishing, what changed is filled in as we go
+  pub fn start(config: &Config) -> Deploy {
+    let workdir = shellexpand::full(&config.workdir).expect("Error reading workdir").to_string();
+    let commit = git(&workdir, &["rev-parse", "HEAD"]);
+    let uncommitted = commit.is_some() && git(&workdir, &["status", "--porcelain"]).map(|s| !s.is_empty()).unwrap_or(false);
+    let site = [&config.site_title, &config.site_url, &config.server_name].iter()
+      .find(|s| !s.is_empty())
+      .map(|s| s.to_string())
+      .unwrap_or_default();
+    Deploy {
+      time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+      site,
+      method: config.publish_method.clone(),
+      target: target(config),
+      target_name: Some(config.target.clone()).filter(|t| !t.is_empty()),
+      release: None,
+      commit,
+      uncommitted,
+      changes: None,
+      newest_post: newest_post(config).map(|(title, link)| Post { title, link }),
+      social: Vec::new()
+    }
+  }
+
+  fn when(&self) -> String {
+    DateTime::parse_from_rfc3339(&self.time)
+      .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+      .unwrap_or_else(|_| self.time.clone())
+  }
+
+  fn files(&self) -> String {
+    match &self.changes {
+      Some(c) => format!("+{} ~{} -{}", c.added.len(), c.updated.len(), c.deleted.len()),
+      None => String::from("?")
+    }
+  }
+}
+
+pub fn load() -> Vec<Deploy> {
+  fs::read_to_string(history_file())
+    .unwrap_or_default()
+    .lines()
+    .filter_map(|l| serde_json::from_str(l).ok())
+    .collect()
+}
+
+pub fn record(deploy: &Deploy) -> io::Result<()> {
+  let mut file = OpenOptions::new().create(true).append(true).open(history_file())?;
+  writeln!(file, "{}", serde_json::to_string(deploy).map_err(io::Error::other)?)?;
+  RECORDED.store(true, Ordering::SeqCst);
+  Ok(())
+}
+
+// social posts are sent after publishing, so they're added to the deploy we just recorded
+// announcing on its own, or after a publish with nothing to publish, isn't part of any deploy
+pub fn add_social(platform: &str, url: &str) -> io::Result<()> {
+  if !RECORDED.load(Ordering::SeqCst) {
+    return Ok(())
+  }
+  let mut deploys = load();
+  if let Some(last) = deploys.last_mut() {
+    last.social.push(SocialPost { platform: platform.to_string(), url: url.to_string() });
+    let mut lines = Vec::new();
+    for deploy in &deploys {
+      lines.push(serde_json::to_string(deploy).map_err(io::Error::other)?);
+    }
+    fs::write(history_file(), [lines.join("\n"), String::from("\n")].concat())?;
+  }
+  Ok(())
+}
+
+fn show(number: usize, deploy: &Deploy) {
+  println!("Deploy {}", number);
+  println!("Published:   {}", deploy.when());
+  println!("Site:        {}", deploy.site);
+  println!("Method:      {}", deploy.method);
+  match &deploy.target_name {
+    Some(name) => println!("Target:      {} ({})", name, deploy.target),
+    None => println!("Target:      {}", deploy.target)
+  }
+  if let Some(release) = &deploy.release {
+    println!("Release:     {}", release);
+  }
+  if let Some(commit) = &deploy.commit {
+    println!("Commit:      {}{}", commit, if deploy.uncommitted { " (with uncommitted changes)" } else { "" });
+  }
+  if let Some(post) = &deploy.newest_post {
+    println!("Newest post: {} {}", post.title, post.link);
+  }
+  for social in &deploy.social {
+    println!("Posted to {}: {}", social.platform, social.url);
+  }
+  match &deploy.changes {
+    Some(c) => {
+      println!("Files:       {}\n", changes::summary(c));
+      changes::show(c);
+    },
+    None => println!("Files:       unknown, your publish command doesn't use rsync")
+  }
+}
+
+// lists every deploy, or shows one of them in full
+pub fn deploys(which: Option<&str>, json: bool) -> io::Result<bool> {
+  let deploys = load();
+  match which {
+    None if json => println!("{}", serde_json::to_string_pretty(&deploys).map_err(io::Error::other)?),
+    None if deploys.is_empty() => println!("Nothing has been published yet"),
+    None => {
+      for (i, deploy) in deploys.iter().enumerate().rev() {
+        let post = deploy.newest_post.as_ref().map(|p| p.title.as_str()).unwrap_or("");
+        let target = deploy.target_name.as_ref().unwrap_or(&deploy.target);
+        println!("{:>4}  {}  {:<12} {}  {}", i + 1, deploy.when(), deploy.files(), target, post);
+      }
+    },
+    Some(which) => {
+      let deploy = which.parse::<usize>().ok().filter(|n| *n > 0).and_then(|n| deploys.get(n - 1));
+      match deploy {
+        Some(d) if json => println!("{}", serde_json::to_string_pretty(d).map_err(io::Error::other)?),
+        Some(d) => show(which.parse().unwrap_or_default(), d),
+        None => {
+          eprintln!("😭 There is no deploy {}. Use 'letters deploys' to list them", which);
+          return Ok(false)
+        }
+      }
+    }
+  }
+  Ok(true)
+}
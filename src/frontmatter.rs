@@ -0,0 +1,210 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone, Utc};
+use serde::Deserializer;
+use serde_derive::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// The frontmatter 'letters write' creates, plus a few common extras
+// Everything is optional here so that a missing value can be reported rather than failing to parse
+
+#[derive(Deserialize, Default)]
+pub struct Image {
+  #[serde(default)]
+  pub photo: String,
+  #[serde(default)]
+  pub description: String
+}
+
+#[derive(Deserialize, Default)]
+pub struct FrontMatter {
+  #[serde(default)]
+  pub title: String,
+  #[serde(default)]
+  pub subtitle: String,
+  #[serde(default)]
+  pub summary: String,
+  #[serde(default)]
+  pub author: String,
+  #[serde(default, deserialize_with = "string_or_list")]
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub date: String,
+  #[serde(default)]
+  pub draft: bool,
+  pub image: Option<Image>,
+  #[serde(default, deserialize_with = "string_or_list")]
+  pub images: Vec<String>
+}
+
+// eleventy allows 'tags: post' as well as 'tags: [post, other]'
+fn string_or_list<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<String>, D::Error> {
+  let value: serde_yaml::Value = serde::Deserialize::deserialize(d)?;
+  Ok(match value {
+    serde_yaml::Value::String(s) => vec![s],
+    serde_yaml::Value::Sequence(seq) => seq.iter().filter_map(|v| v.as_str()).map(String::from).collect(),
+    _ => Vec::new()
+  })
+}
+
+impl FrontMatter {
+  // dates are either full RFC 3339 timestamps (as 'letters write' creates) or just a day
+  pub fn date(&self) -> Option<DateTime<FixedOffset>> {
+    let d = self.date.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(d) {
+      return Some(dt)
+    }
+    NaiveDate::parse_from_str(d, "%Y-%m-%d")
+      .ok()
+      .and_then(|nd| nd.and_hms_opt(0, 0, 0))
+      .map(|ndt| Utc.from_utc_datetime(&ndt).into())
+  }
+}
+
+pub struct Post {
+  pub path: PathBuf,
+  pub slug: String,
+  pub front: FrontMatter,
+  pub body: String
+}
+
+impl Post {
+  // where the post is published, relative to the site root, e.g. posts/my-post for posts/my-post/index.html
+  // the builtin generator puts every post at the top level, other SSGs keep the directories posts are in
+  pub fn url_path(&self, input: &Path, ssg_type: &str) -> String {
+    if ssg_type == "builtin" {
+      return self.slug.clone()
+    }
+    let relative = self.path.strip_prefix(input).unwrap_or(&self.path).with_extension("");
+    let relative = relative.to_string_lossy().replace('\\', "/").to_lowercase();
+    match relative.strip_suffix("/index") {
+      Some(dir) => dir.to_string(),
+      None => relative
+    }
+  }
+}
+
+// split a markdown file into the frontmatter and the body, between two 'fence' lines
+fn split<'a>(contents: &'a str, fence: &str) -> Option<(&'a str, &'a str)> {
+  let rest = contents.strip_prefix(fence)?;
+  let end = rest.find(&["\n", fence].concat())?;
+  let front = &rest[..end];
+  let body = match rest[end + fence.len() + 1..].split_once('\n') {
+    Some((_, b)) => b,
+    None => ""
+  };
+  Some((front, body))
+}
+
+// TOML frontmatter is turned into the same thing as YAML, so the rest of lette.rs doesn't need to care which it was
+fn toml_to_yaml(value: toml::Value) -> serde_yaml::Value {
+  match value {
+    toml::Value::String(s) => serde_yaml::Value::String(s),
+    toml::Value::Integer(i) => serde_yaml::Value::Number(i.into()),
+    toml::Value::Float(f) => serde_yaml::Value::Number(f.into()),
+    toml::Value::Boolean(b) => serde_yaml::Value::Bool(b),
+    toml::Value::Datetime(d) => serde_yaml::Value::String(d.to_string()),
+    toml::Value::Array(a) => serde_yaml::Value::Sequence(a.into_iter().map(toml_to_yaml).collect()),
+    toml::Value::Table(t) => serde_yaml::Value::Mapping(
+      t.into_iter().map(|(k, v)| (serde_yaml::Value::String(k), toml_to_yaml(v))).collect()
+    )
+  }
+}
+
+// the frontmatter and the body of a markdown file
+// frontmatter is YAML between --- lines, or TOML between +++ lines (as Hugo does)
+// a file without any frontmatter is all body
+pub fn parse(contents: &str) -> Result<(serde_yaml::Value, &str), String> {
+  if let Some((yaml, body)) = split(contents, "---") {
+    let value = serde_yaml::from_str(yaml).map_err(|e| format!("frontmatter is not valid YAML: {}", e))?;
+    return Ok((value, body))
+  }
+  if let Some((toml, body)) = split(contents, "+++") {
+    let value = toml::from_str(toml).map_err(|e| format!("frontmatter is not valid TOML: {}", e))?;
+    return Ok((toml_to_yaml(value), body))
+  }
+  Ok((serde_yaml::Value::Null, contents))
+}
+
+pub fn is_markdown(path: &Path) -> bool {
+  matches!(path.extension().and_then(|e| e.to_str()), Some("md") | Some("markdown"))
+}
+
+pub fn read(path: &Path) -> io::Result<Post> {
+  let contents = fs::read_to_string(path)?;
+  let slug = path.file_stem()
+    .map(|s| s.to_string_lossy().to_lowercase())
+    .unwrap_or_default();
+  let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e));
+  let (value, body) = parse(&contents).map_err(invalid)?;
+  let front = match value {
+    serde_yaml::Value::Null => FrontMatter::default(),
+    value => serde_yaml::from_value(value).map_err(|e| invalid(format!("frontmatter is not valid: {}", e)))?
+  };
+  let body = body.to_string();
+  Ok(Post { path: path.to_path_buf(), slug, front, body })
+}
+
+// every markdown file in a directory and its subdirectories
+pub fn markdown_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+  let mut files = Vec::new();
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      files.append(&mut markdown_files(&path)?);
+    } else if is_markdown(&path) {
+      files.push(path);
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+pub fn read_all(dir: &Path) -> io::Result<Vec<Post>> {
+  markdown_files(dir)?.iter().map(|p| read(p)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_yaml_frontmatter() {
+    let (front, body) = parse("---\ntitle: Hello\ntags: [a, b]\n---\nThe body\n").unwrap();
+    assert_eq!(front["title"].as_str(), Some("Hello"));
+    assert_eq!(front["tags"][1].as_str(), Some("b"));
+    assert_eq!(body, "The body\n");
+  }
+
+  #[test]
+  fn parses_toml_frontmatter() {
+    let (front, body) = parse("+++\ntitle = \"Hello\"\ndate = 2021-03-14T09:30:00+11:00\ndraft = true\n+++\nThe body\n").unwrap();
+    let front: FrontMatter = serde_yaml::from_value(front).unwrap();
+    assert_eq!(front.title, "Hello");
+    assert!(front.draft);
+    assert_eq!(front.date().map(|d| d.to_rfc3339()).as_deref(), Some("2021-03-14T09:30:00+11:00"));
+    assert_eq!(body, "The body\n");
+  }
+
+  #[test]
+  fn no_frontmatter_is_all_body() {
+    let (front, body) = parse("Just text\n---\n").unwrap();
+    assert!(front.is_null());
+    assert_eq!(body, "Just text\n---\n");
+  }
+
+  #[test]
+  fn reports_invalid_frontmatter() {
+    assert!(parse("---\ntitle: [oops\n---\n").is_err());
+    assert!(parse("+++\ntitle = \n+++\n").is_err());
+  }
+
+  #[test]
+  fn url_paths() {
+    let post = |path: &str| Post { path: PathBuf::from(path), slug: String::from("same"), front: FrontMatter::default(), body: String::new() };
+    let input = Path::new("/blog/content");
+    assert_eq!(post("/blog/content/posts/Same.md").url_path(input, "hugo"), "posts/same");
+    assert_eq!(post("/blog/content/posts/same/index.md").url_path(input, "eleventy"), "posts/same");
+    assert_eq!(post("/blog/content/posts/Same.md").url_path(input, "builtin"), "same");
+  }
+}
@@ -0,0 +1,147 @@
+use crate::changes::{self, Changes};
+use crate::{newest_post, Config};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use subprocess::{Exec, ExitStatus, Redirection};
+
+// Publishes by committing the output directory to a branch and pushing it, e.g. for GitHub Pages
+// The commits are made in a separate git directory under ~/.letters_git, with output as its work tree,
+// so your own repository and working tree are never touched
+
+// what a branch with no commits is compared to
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+struct Repo {
+  dir: PathBuf,
+  output: PathBuf,
+  // the repository we push to, a URL or a path
+  remote: String,
+  // from [env], e.g. GIT_SSH_COMMAND
+  env: Vec<(String, String)>
+}
+
+impl Repo {
+  fn open(config: &Config) -> io::Result<Repo> {
+    if config.git.repo.is_empty() {
+      return Err(io::Error::other("set 'repo' in the [git] table of your config file to publish with git"))
+    }
+    // one git directory for each repo and branch we publish to
+    let id = format!("{:x}", Sha256::digest([config.git.repo.as_str(), "\n", &config.git.branch].concat()));
+    let dir = PathBuf::from(shellexpand::tilde("~/.letters_git").as_ref()).join(&id[..16]);
+    let output = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+    let mut remote = shellexpand::tilde(&config.git.repo).to_string();
+    // git runs in output, so a relative path to a repository has to be made absolute first
+    if !remote.contains(':') && Path::new(&remote).is_relative() {
+      remote = env::current_dir()?.join(&remote).to_string_lossy().to_string();
+    }
+    let env = config.env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let repo = Repo { dir, output, remote, env };
+    if !repo.dir.join("HEAD").is_file() {
+      fs::create_dir_all(&repo.dir)?;
+      repo.run(&["init", "--quiet"])?;
+    }
+    Ok(repo)
+  }
+
+  // returns whether git worked and what it said
+  fn git(&self, args: &[&str]) -> io::Result<(bool, String)> {
+    let capture = Exec::cmd("git")
+      .arg(["--git-dir=", &self.dir.to_string_lossy()].concat())
+      .arg(["--work-tree=", &self.output.to_string_lossy()].concat())
+      .args(args)
+      // in output, so "." is all of it wherever letters is run from
+      .cwd(&self.output)
+      .env_extend(&self.env)
+      .stdout(Redirection::Pipe)
+      .stderr(Redirection::Merge)
+      .capture()
+      .map_err(|e| io::Error::other(e.to_string()))?;
+    Ok((capture.exit_status == ExitStatus::Exited(0), capture.stdout_str()))
+  }
+
+  fn run(&self, args: &[&str]) -> io::Result<String> {
+    match self.git(args)? {
+      (true, said) => Ok(said),
+      (false, said) => Err(io::Error::other(format!("git {} failed:\n{}", args.join(" "), said.trim_end())))
+    }
+  }
+}
+
+fn configured(repo: &Repo, key: &str) -> bool {
+  matches!(repo.git(&["config", key]), Ok((true, value)) if !value.trim().is_empty())
+}
+
+// stage everything in output on top of the branch as it is now, and list what changed
+fn stage(repo: &Repo, config: &Config) -> io::Result<Changes> {
+  let branch = ["refs/heads/", &config.git.branch].concat();
+  repo.run(&["symbolic-ref", "HEAD", &branch])?;
+  // ls-remote tells a branch that isn't there yet apart from a remote we can't reach,
+  // so a failed fetch is an error rather than a reason to start the branch again
+  let exists = !repo.run(&["ls-remote", "--heads", &repo.remote, &branch])?.trim().is_empty();
+  if exists {
+    repo.run(&["fetch", "--quiet", &repo.remote, &branch])?;
+    repo.run(&["update-ref", &branch, "FETCH_HEAD"])?;
+    repo.run(&["read-tree", "FETCH_HEAD"])?;
+  } else {
+    // the branch doesn't exist yet, so this will be its first commit
+    let _ = repo.git(&["update-ref", "-d", &branch]);
+    repo.run(&["read-tree", "--empty"])?;
+  }
+  // --force so your global gitignore can't leave files out
+  repo.run(&["add", "--all", "--force", "."])?;
+  let base = if repo.git(&["rev-parse", "--verify", "--quiet", "HEAD"])?.0 { "HEAD" } else { EMPTY_TREE };
+  let listed = repo.run(&["diff", "--cached", "--no-renames", "--name-status", base])?;
+  let mut changes = Changes::default();
+  for line in listed.lines() {
+    match line.split_once('\t') {
+      Some(("A", path)) => changes.added.push(path.to_string()),
+      Some(("D", path)) => changes.deleted.push(path.to_string()),
+      Some((_, path)) => changes.updated.push(path.to_string()),
+      None => ()
+    }
+  }
+  Ok(changes)
+}
+
+pub fn plan(config: &Config) -> io::Result<Changes> {
+  stage(&Repo::open(config)?, config)
+}
+
+pub fn publish(config: &Config) -> io::Result<(bool, Changes)> {
+  let repo = Repo::open(config)?;
+  let changes = stage(&repo, config)?;
+  if changes.is_empty() {
+    println!("👌 Nothing to publish, {} is already up to date", config.git.branch);
+    return Ok((true, changes))
+  }
+  let destination = [&config.git.repo, " ", &config.git.branch].concat();
+  if !changes::confirm(config, &changes, &destination) {
+    println!("Not publishing");
+    return Ok((false, changes))
+  }
+
+  let message = match newest_post(config) {
+    Some((title, _)) => format!("Publish site, newest post \"{}\"", title),
+    None => String::from("Publish site")
+  };
+  // use your git identity if you have one
+  let mut args: Vec<String> = Vec::new();
+  if !configured(&repo, "user.name") {
+    args.push(String::from("-c"));
+    args.push(["user.name=", &config.author].concat());
+  }
+  if !configured(&repo, "user.email") {
+    args.push(String::from("-c"));
+    args.push(String::from("user.email=letters@localhost"));
+  }
+  args.extend(["commit", "--quiet", "--message", &message].iter().map(|a| a.to_string()));
+  repo.run(&args.iter().map(|a| a.as_str()).collect::<Vec<_>>())?;
+
+  println!("Pushing {} to {}...", config.git.branch, config.git.repo);
+  let refspec = ["HEAD:refs/heads/", &config.git.branch].concat();
+  repo.run(&["push", "--quiet", &repo.remote, &refspec])?;
+  Ok((true, changes))
+}
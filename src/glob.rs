@@ -0,0 +1,84 @@
+// Matches paths like "img/photo.jpg" against simple globs
+// * matches anything except /, ** matches anything, ? matches one character
+// A glob without a / only looks at the file name, so "*.html" matches "posts/one/index.html"
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+  match pattern.first() {
+    None => text.is_empty(),
+    Some('*') if pattern.get(1) == Some(&'*') => {
+      let rest = &pattern[2..];
+      // "**/" can also match no directories at all
+      if rest.first() == Some(&'/') && matches_from(&rest[1..], text) {
+        return true
+      }
+      (0..=text.len()).any(|i| matches_from(rest, &text[i..]))
+    },
+    Some('*') => {
+      let rest = &pattern[1..];
+      for i in 0..=text.len() {
+        if matches_from(rest, &text[i..]) {
+          return true
+        }
+        if text.get(i) == Some(&'/') {
+          break
+        }
+      }
+      false
+    },
+    Some('?') => !text.is_empty() && text[0] != '/' && matches_from(&pattern[1..], &text[1..]),
+    Some(c) => text.first() == Some(c) && matches_from(&pattern[1..], &text[1..])
+  }
+}
+
+pub fn matches(pattern: &str, path: &str) -> bool {
+  let pattern = pattern.trim_start_matches('/');
+  let path = path.trim_start_matches('/');
+  let text = if pattern.contains('/') {
+    path
+  } else {
+    path.rsplit('/').next().unwrap_or(path)
+  };
+  let pattern: Vec<char> = pattern.chars().collect();
+  let text: Vec<char> = text.chars().collect();
+  matches_from(&pattern, &text)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn star_stays_in_one_directory() {
+    assert!(matches("img/*.jpg", "img/photo.jpg"));
+    assert!(!matches("img/*.jpg", "img/2020/photo.jpg"));
+    assert!(matches("*", "index.html"));
+  }
+
+  #[test]
+  fn double_star_crosses_directories() {
+    assert!(matches("img/**/*.jpg", "img/2020/05/photo.jpg"));
+    assert!(matches("img/**/*.jpg", "img/photo.jpg"));
+    assert!(matches("drafts/**", "drafts/one/index.html"));
+    assert!(!matches("drafts/**", "posts/drafts.html"));
+  }
+
+  #[test]
+  fn question_mark_is_one_character() {
+    assert!(matches("post-?.html", "post-1.html"));
+    assert!(!matches("post-?.html", "post-10.html"));
+    assert!(!matches("a?b", "a/b"));
+  }
+
+  #[test]
+  fn patterns_without_a_slash_match_the_file_name() {
+    assert!(matches("*.html", "posts/one/index.html"));
+    assert!(matches("404.html", "404.html"));
+    assert!(!matches("posts", "posts/one/index.html"));
+  }
+
+  #[test]
+  fn leading_slashes_are_ignored() {
+    assert!(matches("/img/*.jpg", "img/photo.jpg"));
+    assert!(matches("img/*.jpg", "/img/photo.jpg"));
+  }
+}
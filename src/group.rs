@@ -0,0 +1,143 @@
+use colol::color;
+use std::fs;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The test server runs in its own process group, so stopping the group also stops
+// anything the test command started (npx -> node -> eleventy etc)
+// The group id is kept here for Ctrl-C, and in a file for 'letters test --stop'
+// along with when the server started, so we never stop something else that has been given the same id since
+// Windows doesn't have process groups, so there taskkill stops the server and everything it started
+
+static RUNNING: AtomicI32 = AtomicI32::new(0);
+
+// how long to wait after SIGTERM before using SIGKILL
+const GRACE: Duration = Duration::from_secs(3);
+
+fn pid_file() -> PathBuf {
+  PathBuf::from(shellexpand::tilde("~/.letters_test.pid").as_ref())
+}
+
+// when a process started, or None if it isn't running
+#[cfg(unix)]
+fn started(pid: i32) -> Option<String> {
+  let output = Command::new("ps")
+    .args(["-o", "lstart=", "-p", &pid.to_string()])
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+  let start = String::from_utf8_lossy(&output.stdout).trim().to_string();
+  if output.status.success() && !start.is_empty() { Some(start) } else { None }
+}
+
+#[cfg(not(unix))]
+fn started(_pid: i32) -> Option<String> {
+  None
+}
+
+#[cfg(unix)]
+fn alive(pgid: i32) -> bool {
+  // reap any of our own children in the group so they don't count as running
+  unsafe {
+    while libc::waitpid(-pgid, std::ptr::null_mut(), libc::WNOHANG) > 0 {}
+    libc::killpg(pgid, 0) == 0
+  }
+}
+
+pub fn track(pgid: u32) {
+  RUNNING.store(pgid as i32, Ordering::SeqCst);
+  let saved = [pgid.to_string(), started(pgid as i32).unwrap_or_default()].join("\n");
+  if let Err(e) = fs::write(pid_file(), saved) {
+    eprintln!("😬 couldn't save the test server's process id: {}", e);
+  }
+}
+
+// SIGTERM the whole group, then SIGKILL anything still running after GRACE
+#[cfg(unix)]
+fn stop(pgid: i32) -> bool {
+  if pgid <= 0 || !alive(pgid) {
+    return false
+  }
+  unsafe { libc::killpg(pgid, libc::SIGTERM) };
+  let started = Instant::now();
+  while alive(pgid) {
+    if started.elapsed() > GRACE {
+      unsafe { libc::killpg(pgid, libc::SIGKILL) };
+      break
+    }
+    thread::sleep(Duration::from_millis(100));
+  }
+  true
+}
+
+#[cfg(not(unix))]
+fn stop(pid: i32) -> bool {
+  pid > 0 && Command::new("taskkill")
+    .args(["/T", "/F", "/PID", &pid.to_string()])
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()
+    .map(|s| s.success())
+    .unwrap_or(false)
+}
+
+pub fn stop_tracked() {
+  let pgid = RUNNING.swap(0, Ordering::SeqCst);
+  if pgid > 0 {
+    stop(pgid);
+    let _ = fs::remove_file(pid_file());
+  }
+}
+
+// Ctrl-C, or SIGTERM from elsewhere, stops the test server before we exit
+pub fn handle_signals() {
+  let result = ctrlc::set_handler(|| {
+    stop_tracked();
+    println!();
+    println!("goodbye");
+    color!(reset);
+    process::exit(130);
+  });
+  if let Err(e) = result {
+    eprintln!("😬 couldn't listen for Ctrl-C, stop the test server with 'letters test --stop': {}", e);
+  }
+}
+
+// for 'letters test --stop'
+pub fn stop_leftover() -> bool {
+  let saved = fs::read_to_string(pid_file()).unwrap_or_default();
+  let mut lines = saved.lines();
+  let pgid = lines.next().and_then(|p| p.trim().parse::<i32>().ok()).unwrap_or(0);
+  let start = lines.next().unwrap_or("").trim();
+  // if the server is still running it has to be the same process we started
+  // if it has finished but things it started are still running, the group id can't have been reused yet
+  let ours = match started(pgid) {
+    Some(now) => !start.is_empty() && now == start,
+    None => cfg!(unix)
+  };
+  let stopped = pgid > 0 && ours && stop(pgid);
+  let _ = fs::remove_file(pid_file());
+  stopped
+}
+
+// whether something is already listening at the address in a url like http://localhost:8080/blog
+pub fn in_use(url: &str) -> bool {
+  let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+  let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+  let host = host.rsplit_once('@').map(|(_, h)| h).unwrap_or(host);
+  let has_port = host.rsplit_once(':').map(|(_, p)| p.parse::<u16>().is_ok()).unwrap_or(false);
+  let address = if has_port {
+    host.to_string()
+  } else {
+    let port = if scheme == "https" { "443" } else { "80" };
+    [host, ":", port].concat()
+  };
+  match address.to_socket_addrs() {
+    Ok(addrs) => addrs.into_iter().any(|a| TcpStream::connect_timeout(&a, Duration::from_millis(500)).is_ok()),
+    Err(_) => false
+  }
+}
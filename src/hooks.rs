@@ -0,0 +1,53 @@
+use crate::{command, Config};
+
+// Commands from the [hooks] table, run before and after process, publish and write
+// Each hook is a list of commands, run one after another with the shell in workdir
+// If one fails, nothing after it runs, including the rest of the action
+// Hooks are told what's going on with LETTERS_* environment variables
+
+fn commands<'a>(config: &'a Config, hook: &str) -> &'a [String] {
+  let hooks = &config.hooks;
+  match hook {
+    "pre_process" => &hooks.pre_process,
+    "post_process" => &hooks.post_process,
+    "pre_publish" => &hooks.pre_publish,
+    "post_publish" => &hooks.post_publish,
+    "pre_write" => &hooks.pre_write,
+    "post_write" => &hooks.post_write,
+    _ => &[]
+  }
+}
+
+fn expand(path: &str) -> String {
+  shellexpand::full(path).map(|p| p.to_string()).unwrap_or_else(|_| path.to_string())
+}
+
+// returns false if any of the commands failed
+pub fn run(config: &Config, hook: &str, context: &[(&str, String)]) -> subprocess::Result<bool> {
+  let commands = commands(config, hook);
+  if commands.is_empty() {
+    return Ok(true)
+  }
+  let site = if config.site_title.is_empty() { &config.site_url } else { &config.site_title };
+  let mut env = vec![
+    (String::from("LETTERS_HOOK"), hook.to_string()),
+    (String::from("LETTERS_SITE"), site.to_string()),
+    (String::from("LETTERS_SITE_URL"), config.site_url.clone()),
+    (String::from("LETTERS_INPUT"), expand(&config.input)),
+    (String::from("LETTERS_OUTPUT"), expand(&config.output))
+  ];
+  env.extend(context.iter().map(|(k, v)| (k.to_string(), v.clone())));
+
+  for cmd in commands {
+    println!("🪝 {}: {}", hook, cmd);
+    let status = command::shell(cmd, config)
+      .cwd(expand(&config.workdir))
+      .env_extend(&env)
+      .join()?;
+    if !status.success() {
+      eprintln!("😭 The {} hook failed, so stopping here: {}", hook, cmd);
+      return Ok(false)
+    }
+  }
+  Ok(true)
+}
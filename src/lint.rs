@@ -0,0 +1,183 @@
+use colol::color;
+use crate::frontmatter::{self, FrontMatter};
+use crate::Config;
+use chrono::Utc;
+use pulldown_cmark::{Event, Parser, Tag};
+use serde_derive::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+// Checks the frontmatter and content of every post in the input directory
+// Each problem has a rule name, so rules can be turned off with 'ignore' in [lint]
+
+#[derive(Serialize)]
+struct Problem {
+  file: String,
+  rule: &'static str,
+  message: String
+}
+
+// true if the key is there and isn't empty
+fn has_value(yaml: &serde_yaml::Value, key: &str) -> bool {
+  match yaml.get(key) {
+    None | Some(serde_yaml::Value::Null) => false,
+    Some(serde_yaml::Value::String(s)) => !s.trim().is_empty(),
+    Some(serde_yaml::Value::Sequence(s)) => !s.is_empty(),
+    Some(_) => true
+  }
+}
+
+// images in the body, e.g. ![](photo.jpg), with no alt text
+fn images_without_alt(body: &str) -> Vec<String> {
+  let mut missing = Vec::new();
+  let mut current: Option<(String, String)> = None;
+  for event in Parser::new(body) {
+    match event {
+      Event::Start(Tag::Image(_, src, _)) => current = Some((src.to_string(), String::new())),
+      Event::Text(t) | Event::Code(t) => if let Some((_, alt)) = current.as_mut() {
+        alt.push_str(&t);
+      },
+      Event::End(Tag::Image(..)) => if let Some((src, alt)) = current.take() {
+        if alt.trim().is_empty() {
+          missing.push(src);
+        }
+      },
+      _ => ()
+    }
+  }
+  missing
+}
+
+fn lint_post(config: &Config, yaml: &serde_yaml::Value, front: &FrontMatter, body: &str) -> Vec<(&'static str, String)> {
+  let mut found = Vec::new();
+  for key in &config.lint.required {
+    if !has_value(yaml, key) {
+      found.push(("missing", format!("there is no {}", key)));
+    }
+  }
+  if !front.date.trim().is_empty() {
+    match front.date() {
+      None => found.push(("invalid-date", format!("'{}' is not a date lette.rs understands, use e.g. 2021-03-14 or 2021-03-14T09:30:00+11:00", front.date))),
+      Some(date) => if !front.draft && date > Utc::now() {
+        found.push(("future-date", format!("the date {} is in the future, but this isn't a draft", front.date)))
+      }
+    }
+  }
+  let title = front.title.trim().chars().count();
+  if title > config.lint.max_title_length {
+    found.push(("long-title", format!("the title is {} characters long, more than {}", title, config.lint.max_title_length)));
+  }
+  if let Some(image) = &front.image {
+    if !image.photo.trim().is_empty() && image.description.trim().is_empty() {
+      found.push(("image-alt", String::from("the image has no description")));
+    }
+  }
+  for src in images_without_alt(body) {
+    found.push(("image-alt", format!("the image {} has no alt text", src)));
+  }
+  found
+}
+
+pub fn lint(config: &Config, json: bool) -> io::Result<bool> {
+  let input = PathBuf::from(shellexpand::full(&config.input).expect("Error reading input directory").as_ref());
+  let files = frontmatter::markdown_files(&input)?;
+  let mut problems = Vec::new();
+  let mut slugs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+
+  for path in &files {
+    let name = path.strip_prefix(&input).unwrap_or(path).to_string_lossy().to_string();
+    let post = match frontmatter::read(path) {
+      Ok(p) => p,
+      Err(e) => {
+        problems.push(Problem { file: name, rule: "invalid-frontmatter", message: e.to_string() });
+        continue
+      }
+    };
+    slugs.entry(post.url_path(&input, &config.ssg_type)).or_default().push(name.clone());
+    let contents = fs::read_to_string(path)?;
+    let yaml = frontmatter::parse(&contents)
+      .map(|(yaml, _)| yaml)
+      .unwrap_or(serde_yaml::Value::Null);
+    for (rule, message) in lint_post(config, &yaml, &post.front, &post.body) {
+      problems.push(Problem { file: name.clone(), rule, message });
+    }
+  }
+  // two posts that would end up at the same URL, e.g. with the same file name in different directories using the builtin generator
+  for (slug, names) in slugs.iter().filter(|(_, n)| n.len() > 1) {
+    for name in names {
+      let others: Vec<&str> = names.iter().filter(|n| *n != name).map(|n| n.as_str()).collect();
+      problems.push(Problem { file: name.clone(), rule: "duplicate-slug", message: format!("this would be published at /{}/, the same as {}", slug, others.join(", ")) });
+    }
+  }
+
+  problems.retain(|p| !config.lint.ignore.iter().any(|r| r == p.rule));
+  problems.sort_by(|a, b| a.file.cmp(&b.file));
+
+  if json {
+    println!("{}", serde_json::to_string_pretty(&problems).map_err(io::Error::other)?);
+    return Ok(problems.is_empty())
+  }
+  if problems.is_empty() {
+    println!("✅ Checked {} posts, everything looks good", files.len());
+    return Ok(true)
+  }
+  let mut last = "";
+  for problem in &problems {
+    if problem.file != last {
+      color!(yellow);
+      println!("{}", problem.file);
+      color!(reset);
+      last = &problem.file;
+    }
+    println!("  {}: {}", problem.rule, problem.message);
+  }
+  let posts = problems.iter().map(|p| &p.file).collect::<BTreeSet<_>>().len();
+  eprintln!("😭 Found {} problems in {} of {} posts", problems.len(), posts, files.len());
+  Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> Config {
+    toml::from_str("author = \"\"\ninput = \"\"\noutput = \"\"\nworkdir = \"\"\n[commands]\n[social]\n").unwrap()
+  }
+
+  // the problems lint_post finds in a post, by rule
+  fn rules(contents: &str) -> Vec<(&'static str, String)> {
+    let (yaml, body) = frontmatter::parse(contents).unwrap();
+    let front: FrontMatter = serde_yaml::from_value(yaml.clone()).unwrap_or_default();
+    lint_post(&config(), &yaml, &front, body)
+  }
+
+  #[test]
+  fn finds_missing_title_date_and_summary() {
+    let found = rules("---\nlayout: post\n---\nHello\n");
+    let messages: Vec<&str> = found.iter().filter(|(rule, _)| *rule == "missing").map(|(_, m)| m.as_str()).collect();
+    assert_eq!(messages, ["there is no title", "there is no date", "there is no summary"]);
+    assert!(rules("---\ntitle: Hi\ndate: 2021-03-14\nsummary: A post\n---\nHello\n").is_empty());
+  }
+
+  #[test]
+  fn finds_images_without_alt_text() {
+    assert_eq!(images_without_alt("![](one.jpg) and ![  ](two.jpg) but ![A cat](three.jpg)"), ["one.jpg", "two.jpg"]);
+    assert!(images_without_alt("![`code` alt](four.jpg)").is_empty());
+    let found = rules("---\ntitle: Hi\ndate: 2021-03-14\nsummary: A post\n---\n![](photo.jpg)\n");
+    assert_eq!(found, [("image-alt", String::from("the image photo.jpg has no alt text"))]);
+  }
+
+  #[test]
+  fn blank_values_are_missing() {
+    let (yaml, _) = frontmatter::parse("---\nempty: ''\nspaces: '   '\nnothing:\ntags: []\ntitle: ' Hi '\ncount: 0\n---\n").unwrap();
+    assert!(!has_value(&yaml, "empty"));
+    assert!(!has_value(&yaml, "spaces"));
+    assert!(!has_value(&yaml, "nothing"));
+    assert!(!has_value(&yaml, "tags"));
+    assert!(!has_value(&yaml, "absent"));
+    assert!(has_value(&yaml, "title"));
+    assert!(has_value(&yaml, "count"));
+  }
+}
@@ -1,20 +1,43 @@
 use chrono::{SecondsFormat, Utc};
 use clap::{Arg, App, ArgMatches};
 use colol::{color, close_color};
-use subprocess::{Exec, ExitStatus, Popen, PopenConfig};
+use subprocess::{Exec, ExitStatus, NullFile, Popen, PopenConfig, Redirection};
 use itertools::join;
-use reqwest;
 use rss::Channel;
 use std::env;
 use std::ffi::OsString;
 use std::fs;
 use std::io::{BufReader, self, Write};
-use std::path::Path;
-use std::process::Command;
-use std::thread::sleep;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 use serde_derive::Deserialize;
 
+mod builtin;
+mod changes;
+mod check;
+mod command;
+mod deploys;
+mod frontmatter;
+mod git;
+mod glob;
+mod group;
+mod hooks;
+mod lint;
+mod manifest;
+mod ping;
+mod purge;
+mod releases;
+mod rsync;
+mod s3;
+mod serve;
+mod setup;
+mod sftp;
+mod ship;
+mod sitemap;
+mod url;
+mod watch;
+
 // deserialize TOML file
 #[derive(Deserialize)]
 struct Commands {
@@ -23,7 +46,9 @@ struct Commands {
     #[serde(default = "default_publish")]
     publish: String,
     #[serde(default = "default_blank")]
-    test: String
+    test: String,
+    #[serde(default = "default_blank")]
+    watch_hook: String
 }
 
 #[derive(Deserialize)]
@@ -42,17 +67,242 @@ struct Social {
   twitter_access_secret: String
   }
 
+// extra arguments for rsync when publishing
+#[derive(Deserialize, Default, Clone)]
+struct Rsync {
+  #[serde(default)]
+  exclude: Vec<String>,
+  #[serde(default)]
+  chmod: String,
+  #[serde(default)]
+  ssh: String,
+  #[serde(default)]
+  flags: Vec<String>
+}
+
+// file permissions for publishing with sftp
+#[derive(Deserialize, Clone)]
+struct Sftp {
+  #[serde(default = "default_file_mode")]
+  file_mode: String,
+  #[serde(default = "default_dir_mode")]
+  dir_mode: String
+}
+
+impl Default for Sftp {
+  fn default() -> Self {
+    Sftp {
+      file_mode: default_file_mode(),
+      dir_mode: default_dir_mode()
+    }
+  }
+}
+
+#[derive(Deserialize, Clone)]
+struct CacheRule {
+  glob: String,
+  value: String
+}
+
+// where and how to publish with s3
+#[derive(Deserialize, Default, Clone)]
+struct S3 {
+  #[serde(default)]
+  bucket: String,
+  #[serde(default)]
+  prefix: String,
+  #[serde(default)]
+  endpoint: String,
+  #[serde(default)]
+  region: String,
+  path_style: Option<bool>,
+  #[serde(default)]
+  access_key: String,
+  #[serde(default)]
+  secret_key: String,
+  #[serde(default)]
+  cache_control: Vec<CacheRule>
+}
+
+// where to push to with git
+#[derive(Deserialize, Clone)]
+struct Git {
+  #[serde(default)]
+  repo: String,
+  #[serde(default = "default_branch")]
+  branch: String
+}
+
+impl Default for Git {
+  fn default() -> Self {
+    Git {
+      repo: String::new(),
+      branch: default_branch()
+    }
+  }
+}
+
+// which CDN to purge after publishing, and how
+#[derive(Deserialize, Clone)]
+struct Purge {
+  #[serde(default)]
+  provider: String,
+  #[serde(default = "default_scope")]
+  scope: String,
+  #[serde(default)]
+  zone: String,
+  #[serde(default)]
+  token: String,
+  #[serde(default = "default_method")]
+  method: String,
+  #[serde(default)]
+  url: String,
+  #[serde(default)]
+  headers: HashMap<String, String>,
+  #[serde(default)]
+  body: String
+}
+
+impl Default for Purge {
+  fn default() -> Self {
+    Purge {
+      provider: String::new(),
+      scope: default_scope(),
+      zone: String::new(),
+      token: String::new(),
+      method: default_method(),
+      url: String::new(),
+      headers: HashMap::new(),
+      body: String::new()
+    }
+  }
+}
+
+// who to tell about new posts after publishing
+#[derive(Deserialize, Clone)]
+struct Ping {
+  #[serde(default)]
+  websub_hub: String,
+  #[serde(default)]
+  feed_url: String,
+  #[serde(default)]
+  indexnow_key: String,
+  #[serde(default = "default_indexnow_endpoint")]
+  indexnow_endpoint: String
+}
+
+impl Default for Ping {
+  fn default() -> Self {
+    Ping {
+      websub_hub: String::new(),
+      feed_url: String::new(),
+      indexnow_key: String::new(),
+      indexnow_endpoint: default_indexnow_endpoint()
+    }
+  }
+}
+
+// somewhere else to publish to, e.g. a staging server
+// anything not set here comes from the rest of the config file
+#[derive(Deserialize, Clone)]
+struct Target {
+  name: String,
+  site_url: Option<String>,
+  server_name: Option<String>,
+  remote_dir: Option<String>,
+  publish_method: Option<String>,
+  publish: Option<String>,
+  atomic_releases: Option<bool>,
+  keep_releases: Option<usize>,
+  rsync: Option<Rsync>,
+  sftp: Option<Sftp>,
+  s3: Option<S3>,
+  git: Option<Git>,
+  purge: Option<Purge>,
+  ping: Option<Ping>
+}
+
+// the optional steps for 'letters ship'
+#[derive(Deserialize, Default)]
+struct Ship {
+  #[serde(default)]
+  lint: bool,
+  #[serde(default)]
+  check: bool,
+  #[serde(default)]
+  toot: bool,
+  #[serde(default)]
+  tweet: bool
+}
+
+// 'letters sitemap', and whether 'process' makes a sitemap too
+#[derive(Deserialize)]
+struct Sitemap {
+  #[serde(default)]
+  generate: bool,
+  #[serde(default = "default_sitemap_exclude")]
+  exclude: Vec<String>
+}
+
+impl Default for Sitemap {
+  fn default() -> Self {
+    Sitemap {
+      generate: false,
+      exclude: default_sitemap_exclude()
+    }
+  }
+}
+
+// commands to run before and after actions
+#[derive(Deserialize, Default)]
+struct Hooks {
+  #[serde(default)]
+  pre_process: Vec<String>,
+  #[serde(default)]
+  post_process: Vec<String>,
+  #[serde(default)]
+  pre_publish: Vec<String>,
+  #[serde(default)]
+  post_publish: Vec<String>,
+  #[serde(default)]
+  pre_write: Vec<String>,
+  #[serde(default)]
+  post_write: Vec<String>
+}
+
+#[derive(Deserialize)]
+struct Lint {
+  #[serde(default = "default_required")]
+  required: Vec<String>,
+  #[serde(default = "default_max_title_length")]
+  max_title_length: usize,
+  #[serde(default)]
+  ignore: Vec<String>
+}
+
+impl Default for Lint {
+  fn default() -> Self {
+    Lint {
+      required: default_required(),
+      max_title_length: default_max_title_length(),
+      ignore: Vec::new()
+    }
+  }
+}
+
 #[derive(Deserialize)]
 struct Config {
     author: String,
     input: String,
     output: String,
     workdir: String,
+    #[serde(default = "default_blank")]
     remote_dir: String,
     #[serde(default = "default_blank")]
     rss_file: String,
     #[serde(default = "default_blank")]
     unsplash_client_id: String,
+    #[serde(default = "default_blank")]
     server_name: String,
     #[serde(default = "default_blank")]
     test_url: String,
@@ -61,7 +311,67 @@ struct Config {
     #[serde(default = "default_ssg")]
     ssg_type: String,
     #[serde(default = "default_layout")]
-    default_layout: String
+    default_layout: String,
+    #[serde(default = "default_blank")]
+    site_title: String,
+    #[serde(default = "default_blank")]
+    site_url: String,
+    #[serde(default = "default_template_dir")]
+    template_dir: String,
+    #[serde(default)]
+    extra_paths: Vec<String>,
+    #[serde(default = "default_test_timeout")]
+    test_timeout: u64,
+    #[serde(default = "default_blank")]
+    browser: String,
+    #[serde(default = "default_serve_address")]
+    serve_address: String,
+    #[serde(default)]
+    live_reload: bool,
+    #[serde(default)]
+    check_before_publish: bool,
+    #[serde(default)]
+    check_external: bool,
+    #[serde(default)]
+    lint: Lint,
+    #[serde(default = "default_publish_method")]
+    publish_method: String,
+    #[serde(default)]
+    rsync: Rsync,
+    #[serde(default)]
+    sftp: Sftp,
+    #[serde(default)]
+    s3: S3,
+    #[serde(default)]
+    git: Git,
+    #[serde(default)]
+    purge: Purge,
+    #[serde(default)]
+    ping: Ping,
+    #[serde(default = "default_confirm_deletions")]
+    confirm_deletions: usize,
+    #[serde(default)]
+    atomic_releases: bool,
+    #[serde(default = "default_keep_releases")]
+    keep_releases: usize,
+    #[serde(default)]
+    hooks: Hooks,
+    #[serde(default)]
+    ship: Ship,
+    #[serde(default)]
+    sitemap: Sitemap,
+    #[serde(default)]
+    targets: Vec<Target>,
+    #[serde(default = "default_blank")]
+    default_target: String,
+    // the name of the target we're publishing to, if it's one of targets
+    #[serde(skip)]
+    target: String,
+    // --yes, to publish without asking first
+    #[serde(skip)]
+    yes: bool,
+    #[serde(default)]
+    env: HashMap<String, String>
 }
 
 // Derive default values
@@ -70,6 +380,55 @@ fn default_blank() -> String {
     String::from("")
 }
 
+fn default_required() -> Vec<String> {
+  vec![String::from("title"), String::from("date"), String::from("summary")]
+}
+
+// leaves room for the URL and a message in a toot or tweet
+fn default_max_title_length() -> usize {
+  100
+}
+
+fn default_confirm_deletions() -> usize {
+  20
+}
+
+fn default_keep_releases() -> usize {
+  5
+}
+
+fn default_publish_method() -> String {
+  String::from("rsync")
+}
+
+fn default_sitemap_exclude() -> Vec<String> {
+  vec![String::from("404.html")]
+}
+
+fn default_scope() -> String {
+  String::from("changed")
+}
+
+fn default_method() -> String {
+  String::from("POST")
+}
+
+fn default_indexnow_endpoint() -> String {
+  String::from("https://api.indexnow.org/indexnow")
+}
+
+fn default_file_mode() -> String {
+  String::from("644")
+}
+
+fn default_dir_mode() -> String {
+  String::from("755")
+}
+
+fn default_branch() -> String {
+  String::from("gh-pages")
+}
+
 fn default_layout() -> String {
   String::from("post")
 }
@@ -82,123 +441,325 @@ fn default_ssg() -> String {
   String::from("eleventy")
 }
 
-fn open_file(cmd: &str) {
-  Exec::shell(cmd).join().unwrap();
-}
-
-fn setup() {
-
-  fn prep_to_open_file() {
-    let os = env::consts::OS;
-    match os {
-      "macos" => open_file("open ~/.letters.toml"),
-      "linux" | "freebsd" | "openbsd" => open_file("xdg-open ~/.letters.toml"),
-      &_ => ()
-    }
-  }
-
-  fn create_file(path: &str) {
-
-    let mut empty_file = Vec::new();
-    empty_file.push("author = \"\" # your name");
-    empty_file.push("input = \"\" # the input directory for your site i.e. where your markdown files go");
-    empty_file.push("output = \"\" # the directory for processed files for your site i.e. where your html files go. Do NOT include a trailing slash as lette.rs will add this for you");
-    empty_file.push("workdir = \"\" # the base directory for calling your static site commands. Probably the root directory for eleventy, Hugo etc");
-    empty_file.push("remote_dir = \"\" # the directory to rsync files to, on your remote server.");
-    empty_file.push("rss_file = \"\" # filepath to the RSS file in your output directory");
-    empty_file.push("server_name = \"\" # this could be a name if you have set one in ~/.ssh/config, or otherwise an IP address");
-    empty_file.push("\n# All the values below are optional. Remove the '#' to uncomment them if you wish to override the default or set a value\n");
-    empty_file.push("# unsplash_client_id = \"\" # unsplash client ID string");
-    empty_file.push("# test_url = \"\" # if your SSG serves your site locally this should be the localhost URL where you can see it. eleventy and hugo will use their respective defaults if you don't provide a value. ");
-    empty_file.push("# ssg_type = \"\" # your static site generator. Options that will do something are \"hugo\" or \"eleventy\" but you can try something else and see if it works. Defaults to \"eleventy\"");
-    empty_file.push("# default_layout = \"\" # use any string, this will be the value of \"layout\" in your frontmatter. Defaults to \"post\"");
-    empty_file.push("\n");
-    empty_file.push("[commands]");
-    empty_file.push("# You can override the defaults by setting one of the values below, but if using Hugo or Eleventy you don't need to do so.");
-    empty_file.push("# process = \"\" # command to process files");
-    empty_file.push("# publish = \"\" # defaults to 'rsync -roptO --del --quiet'");
-    empty_file.push("# test = \"\" # command to serve site locally (if your SSG enables that)");
-    empty_file.push("\n");
-    empty_file.push("[social]");
-    empty_file.push("# uncomment and set values below as needed");
-    empty_file.push("# mastodon_access_token = \"\" ");
-    empty_file.push("# mastodon_base_url = \"\" # e.g. https://example.com");
-    empty_file.push("# twitter_consumer_key = \"\"");
-    empty_file.push("# twitter_consumer_secret = \"\"");
-    empty_file.push("# twitter_access_token = \"\"");
-    empty_file.push("# twitter_access_secret = \"\"");
-    let conf = empty_file.join("\n").to_string()  ;
-
-    match fs::write(path, conf) {
-      Ok(_) => prep_to_open_file(),
-      Err(e) => println!("Error opening file {}: {}", path, e)
-    };
-  }
+fn default_test_timeout() -> u64 {
+  30
+}
 
-  let path = shellexpand::full("~/.letters.toml");
-  match path {
-    Ok(p) => choose_file(p.as_ref()),
-    Err(e) => eprintln!("There was an error reading the default config path:, {}", e)
-  }
+fn default_serve_address() -> String {
+  String::from("localhost:8080")
+}
+
+fn default_template_dir() -> String {
+  String::from("templates")
+}
 
-  fn choose_file(f: &str) {
-    let file = fs::OpenOptions::new()
-    .write(true)
-    .create_new(true)
-    .open(f);
+// how much of the SSG output to show when processing fails
+const FAILURE_LINES: usize = 20;
 
-    match file {
-    // no error means the file did not exist
-    Ok(_) => create_file(f),
-    // error here means the file exists
-    Err(_error) => prep_to_open_file()
-    };
+// everything the site is built from: if none of these change, neither will the output
+fn source_paths(config: &Config) -> Vec<PathBuf> {
+  let wd = PathBuf::from(shellexpand::full(&config.workdir).expect("Error reading working directory").as_ref());
+  let mut paths = vec![PathBuf::from(shellexpand::full(&config.input).expect("Error reading input directory").as_ref())];
+  for p in &config.extra_paths {
+    paths.push(wd.join(shellexpand::full(p).expect("Error reading extra_paths").as_ref()));
   }
+  if config.ssg_type == "builtin" {
+    paths.push(wd.join(shellexpand::full(&config.template_dir).expect("Error reading template directory").as_ref()));
+  }
+  // changing the config (e.g. the process command) should trigger a rebuild too
+  paths.push(PathBuf::from(shellexpand::full("~/.letters.toml").unwrap().as_ref()));
+  paths
 }
 
-fn process(config: &Config)  -> subprocess::Result<bool> {
+// modification times of a file, or every file in a directory and its subdirectories
+// comparing before and after processing tells us how many files were generated
+fn snapshot(path: &Path, files: &mut HashMap<PathBuf, SystemTime>) {
+  if path.is_dir() {
+    if let Ok(entries) = fs::read_dir(path) {
+      for entry in entries.filter_map(|e| e.ok()) {
+        snapshot(&entry.path(), files);
+      }
+    }
+  } else if let Ok(modified) = fs::metadata(path).and_then(|m| m.modified()) {
+    files.insert(path.to_path_buf(), modified);
+  }
+}
+
+fn process(config: &Config, verbose: bool, force: bool) -> subprocess::Result<bool> {
 
   let wd = shellexpand::full(&config.workdir)
     .expect("Error reading working directory")
     .to_string();
+  let output = shellexpand::full(&config.output)
+    .expect("Error reading output directory")
+    .to_string();
 
   // deal with possible empty config value
   // and set default command depending on ssg_type
 
   let cc = &config.commands.process;
-  let ssg = &config.ssg_type.as_str();
-  let commands;
-  if cc == &String::from("") {
-    commands = match ssg {
-      &"hugo" => "hugo --quiet",
-      _ => "eleventy --input=input --quiet"
+  let ssg = config.ssg_type.as_str();
+  if !hooks::run(config, "pre_process", &[])? {
+    return Ok(false)
+  }
+  let mut before = HashMap::new();
+  snapshot(Path::new(&output), &mut before);
+
+  // skip processing if nothing has changed since the last successful run
+  // unless the output has gone missing in the meantime
+  let manifest_file = PathBuf::from(shellexpand::full("~/.letters_manifest").unwrap().as_ref());
+  let mut sources = manifest::build(&source_paths(config))?;
+  // in case the output directory lives inside one of the source directories
+  sources.retain(|path, _| !Path::new(path).starts_with(&output));
+  if !force && !before.is_empty() {
+    let changed = manifest::changes(&manifest::load(&manifest_file), &sources);
+    if changed == 0 {
+      println!("👌 Nothing has changed since the last time you processed, skipping. Use --force to process anyway.");
+      return Ok(true)
     }
-  } else {
-    commands = cc.as_str();
   }
+  let timer = Instant::now();
 
-  let processed = Exec::shell(commands)
-    .cwd(wd)
-    .join()?;
-  match processed {
-    ExitStatus::Exited(code) => if code == 0 {
-      Ok(true)
+  let processed = if cc.is_empty() && ssg == "builtin" {
+    // no external generator needed
+    builtin::build(config)?
+  } else {
+    // the defaults are quiet unless we want to see everything
+    let commands = if cc.is_empty() {
+      match (ssg, verbose) {
+        ("hugo", false) => "hugo --quiet",
+        ("hugo", true) => "hugo",
+        (_, false) => "eleventy --input=input --quiet",
+        (_, true) => "eleventy --input=input"
+      }
     } else {
-      Ok(false)
-    },
-    _ => Ok(false)
+      cc.as_str()
+    };
+
+    if verbose {
+      // stream output straight to the terminal
+      command::build(commands, &[], config)?
+        .cwd(&wd)
+        .join()?
+        .success()
+    } else {
+      // capture output so we only show it if something goes wrong
+      let captured = command::build(commands, &[], config)?
+        .cwd(&wd)
+        .stdout(Redirection::Pipe)
+        .stderr(Redirection::Merge)
+        .capture()?;
+      if !captured.success() {
+        let out = captured.stdout_str();
+        let lines: Vec<&str> = out.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(FAILURE_LINES)..];
+        if !tail.is_empty() {
+          eprintln!("Last {} lines of output from '{}':\n", tail.len(), commands);
+          eprintln!("{}\n", tail.join("\n"));
+        }
+      }
+      captured.success()
+    }
+  };
+
+  let elapsed = timer.elapsed().as_secs_f32();
+  if processed {
+    manifest::save(&manifest_file, &sources)?;
+    // the site is processed either way, so this is only a warning
+    if config.sitemap.generate {
+      if let Err(e) = sitemap::build(config) {
+        eprintln!("😬 Couldn't make sitemap.xml: {}", e);
+      }
+    }
+    let mut after = HashMap::new();
+    snapshot(Path::new(&output), &mut after);
+    let generated = after.iter().filter(|(path, modified)| before.get(*path) != Some(modified)).count();
+    println!("✨ Processed in {:.1}s, {} file{} generated", elapsed, generated, if generated == 1 { "" } else { "s" });
+  } else {
+    eprintln!("😭 Processing failed after {:.1}s", elapsed);
+  }
+  Ok(processed && hooks::run(config, "post_process", &[])?)
+}
+
+// use the settings from one of [[targets]], chosen with --target or default_target
+fn select_target(mut config: Config, name: Option<&str>) -> Result<Config, String> {
+  let name = match name {
+    Some(n) => n.to_string(),
+    None if !config.default_target.is_empty() => config.default_target.clone(),
+    None => return Ok(config)
+  };
+  let target = match config.targets.iter().find(|t| t.name == name) {
+    Some(t) => t.clone(),
+    None => {
+      let names: Vec<&str> = config.targets.iter().map(|t| t.name.as_str()).collect();
+      return if names.is_empty() {
+        Err(format!("There is no target called '{}', add it to [[targets]] in your config file", name))
+      } else {
+        Err(format!("There is no target called '{}'. Your targets are: {}", name, names.join(", ")))
+      }
+    }
+  };
+  if let Some(v) = target.site_url { config.site_url = v }
+  if let Some(v) = target.server_name { config.server_name = v }
+  if let Some(v) = target.remote_dir { config.remote_dir = v }
+  if let Some(v) = target.publish_method { config.publish_method = v }
+  if let Some(v) = target.publish { config.commands.publish = v }
+  if let Some(v) = target.atomic_releases { config.atomic_releases = v }
+  if let Some(v) = target.keep_releases { config.keep_releases = v }
+  if let Some(v) = target.rsync { config.rsync = v }
+  if let Some(v) = target.sftp { config.sftp = v }
+  if let Some(v) = target.s3 { config.s3 = v }
+  if let Some(v) = target.git { config.git = v }
+  // purging and pinging are about one particular site, so a target only does them if it says how
+  config.purge = target.purge.unwrap_or_default();
+  config.ping = target.ping.unwrap_or_default();
+  config.target = name;
+  Ok(config)
+}
+
+// where the site lives on the server now, which is what we compare with before publishing
+fn live_dir(config: &Config) -> String {
+  if config.atomic_releases {
+    releases::current_dir(config)
+  } else {
+    shellexpand::full(&config.remote_dir).expect("Error reading remote directory").to_string()
+  }
+}
+
+// rsync and sftp publish to remote_dir on server_name, which s3 and git don't need
+fn has_server(config: &Config) -> bool {
+  let missing: Vec<&str> = [("server_name", &config.server_name), ("remote_dir", &config.remote_dir)].iter()
+    .filter(|(_, value)| value.is_empty())
+    .map(|(key, _)| *key)
+    .collect();
+  if !missing.is_empty() {
+    eprintln!("😭 Set {} in your config file to publish with {}", missing.join(" and "), config.publish_method);
+  }
+  missing.is_empty()
+}
+
+// the source and destination for the publish command
+fn publish_args(config: &Config, remote: &str) -> Vec<String> {
+  let output = [shellexpand::full(&config.output).expect("Error reading output directory").as_ref(), "/"].concat();
+  let destination = [&config.server_name, ":", remote].concat();
+  let mut args = Vec::new();
+  if rsync::is_rsync(&config.commands.publish, config) {
+    args = rsync::options(config, remote);
+  } else if rsync::configured(config) {
+    eprintln!("😬 Your [rsync] settings are ignored because your publish command doesn't use rsync");
   }
+  args.push(output);
+  args.push(destination);
+  args
 }
 
 fn publish(config: &Config) -> subprocess::Result<bool> {
+  Ok(deploy(config)?.is_some())
+}
+
+// publishes, returning what was published, or None if it didn't work
+fn deploy(config: &Config) -> subprocess::Result<Option<deploys::Deploy>> {
+  if !config.target.is_empty() {
+    println!("Publishing to {}...", config.target);
+  }
+  let mut deploy = deploys::Deploy::start(config);
+  let (title, link) = deploy.newest_post.as_ref().map(|p| (p.title.clone(), p.link.clone())).unwrap_or_default();
+  let context = [
+    ("LETTERS_TARGET", config.target.clone()),
+    ("LETTERS_POST_TITLE", title),
+    ("LETTERS_POST_URL", link)
+  ];
+  if !hooks::run(config, "pre_publish", &context)? {
+    return Ok(None)
+  }
+  let published = upload(config, &mut deploy)?;
+  // "nothing to publish" isn't worth remembering
+  if published && !deploy.changes.as_ref().map(|c| c.is_empty()).unwrap_or(false) {
+    if let Err(e) = deploys::record(&deploy) {
+      eprintln!("😬 Couldn't add this to your deploy history: {}", e);
+    }
+  }
+  // the site is published either way, so this is only a warning
+  if published {
+    if let Err(e) = purge::purge(config, deploy.changes.as_ref()) {
+      eprintln!("😬 Couldn't purge your CDN, it might show old pages for a while: {}", e);
+    }
+    ping::ping(config, deploy.changes.as_ref());
+  }
+  if published && hooks::run(config, "post_publish", &context)? {
+    Ok(Some(deploy))
+  } else {
+    Ok(None)
+  }
+}
+
+fn upload(config: &Config, deploy: &mut deploys::Deploy) -> subprocess::Result<bool> {
+
+  if config.check_before_publish && !check::check(config)? {
+    eprintln!("Not publishing because of broken links. Fix them and try again, or set check_before_publish = false");
+    return Ok(false)
+  }
+
+  if matches!(config.publish_method.as_str(), "rsync" | "sftp") && !has_server(config) {
+    return Ok(false)
+  }
+  // every publish method says whether it worked, and what changed
+  let (published, changes) = match config.publish_method.as_str() {
+    "rsync" => return publish_rsync(config, deploy),
+    "sftp" => sftp::publish(config)?,
+    "s3" => s3::publish(config)?,
+    "git" => git::publish(config)?,
+    method => {
+      eprintln!("😭 '{}' isn't a publish_method lette.rs knows about. Use \"rsync\", \"sftp\", \"s3\" or \"git\"", method);
+      return Ok(false)
+    }
+  };
+  deploy.changes = Some(changes);
+  Ok(published)
+}
+
+fn publish_rsync(config: &Config, deploy: &mut deploys::Deploy) -> subprocess::Result<bool> {
+
+  if config.atomic_releases && !rsync::is_rsync(&config.commands.publish, config) {
+    eprintln!("😭 atomic_releases only works when your publish command uses rsync");
+    return Ok(false)
+  }
+
+  let args = publish_args(config, &live_dir(config));
+  let destination = &args[args.len() - 1];
+  if let Some(changes) = rsync::preview(&config.commands.publish, &args, config)? {
+    if !changes::confirm(config, &changes, destination) {
+      println!("Not publishing");
+      return Ok(false)
+    }
+    deploy.changes = Some(changes);
+  }
+
+  // a new release is uploaded next to the live one, with unchanged files hard linked from it
+  let release = if config.atomic_releases { Some(releases::new_name()) } else { None };
+  deploy.release = release.clone();
+  let args = match &release {
+    Some(name) => {
+      let (_, current) = releases::list(config)?;
+      let mut args = publish_args(config, &releases::release_dir(config, name));
+      // relative to the release, since rsync reads a relative --link-dest from the destination
+      if current.is_some() {
+        args.insert(0, String::from("--link-dest=../../current/"));
+      }
+      args
+    },
+    None => args
+  };
 
-  let remote = shellexpand::full(&config.remote_dir).expect("Error reading remote directory").to_string();
-  let output = [&shellexpand::full(&config.output).expect("Error reading output directory").to_string(), "/"].concat();
-  let concatenated = [&config.commands.publish, " ", &output, " ", &config.server_name, ":", &remote].concat();
-  let publishing = Exec::shell(&concatenated)
+  let publishing = command::build(&config.commands.publish, &args, config)?
     .join()?;
   match publishing {
     ExitStatus::Exited(code) => if code == 0 {
+      if let Some(name) = release {
+        releases::switch(config, &name)?;
+        let removed = releases::prune(config)?;
+        println!("Release {} is live{}", name, if removed > 0 { format!(", removed {} old releases", removed) } else { String::new() });
+      }
       Ok(true)
     } else {
       Ok(false)
@@ -207,81 +768,251 @@ fn publish(config: &Config) -> subprocess::Result<bool> {
   }
 }
 
+fn dry_run(config: &Config) -> subprocess::Result<bool> {
+  if !matches!(config.publish_method.as_str(), "s3" | "git") && !has_server(config) {
+    return Ok(false)
+  }
+  let preview = match config.publish_method.as_str() {
+    "sftp" => Some(sftp::plan(config)?.0),
+    "s3" => Some(s3::plan(config)?),
+    "git" => Some(git::plan(config)?),
+    _ => rsync::preview(&config.commands.publish, &publish_args(config, &live_dir(config)), config)?
+  };
+  match preview {
+    None => {
+      eprintln!("😬 --dry-run only works with sftp, s3 and git, or when your publish command uses rsync");
+      Ok(false)
+    },
+    Some(changes) => {
+      if changes.is_empty() {
+        println!("👌 Nothing to publish, the server is already up to date");
+      } else {
+        changes::show(&changes);
+        println!("{}. This was a dry run, nothing was published.", changes::summary(&changes));
+      }
+      Ok(true)
+    }
+  }
+}
+
 fn quote(s: &str) -> String {
   let mut q = String::new();
-  q.push_str("\"");
-  q.push_str(&s.to_lowercase().trim());
-  q.push_str("\"");
+  q.push('"');
+  q.push_str(s.to_lowercase().trim());
+  q.push('"');
   q
 }
 
 
-fn test(config: &Config) -> subprocess::Result<()>{
+// show the end of a log file, e.g. when the test server fails to start
+fn show_log(path: &Path) {
+  let log = fs::read_to_string(path).unwrap_or_default();
+  let lines: Vec<&str> = log.lines().collect();
+  let tail = &lines[lines.len().saturating_sub(FAILURE_LINES)..];
+  if !tail.is_empty() {
+    eprintln!("Last {} lines of output from the test server:\n", tail.len());
+    eprintln!("{}\n", tail.join("\n"));
+  }
+}
+
+// keep trying the test URL until something answers, the server quits, or we run out of time
+fn wait_for_server(session: &mut Popen, url: &str, timeout: Duration) -> Result<(), String> {
+  let client = reqwest::blocking::Client::builder()
+    .timeout(Duration::from_secs(2))
+    .build()
+    .map_err(|e| e.to_string())?;
+  let started = Instant::now();
+  loop {
+    if let Some(status) = session.poll() {
+      return Err(format!("the test server stopped before {} was ready ({:?})", url, status))
+    }
+    // any response at all means the server is up, even if it's a 404
+    if client.get(url).send().is_ok() {
+      return Ok(())
+    }
+    if started.elapsed() > timeout {
+      return Err(format!("{} didn't respond within {} seconds", url, timeout.as_secs()))
+    }
+    std::thread::sleep(Duration::from_millis(250));
+  }
+}
+
+// try the configured browser, then $BROWSER, then the system default
+fn open_browser(config: &Config, url: &str) -> bool {
+  let mut browsers = Vec::new();
+  if !config.browser.is_empty() {
+    browsers.push(config.browser.clone());
+  }
+  // $BROWSER can be a colon separated list
+  if let Ok(b) = env::var("BROWSER") {
+    browsers.extend(b.split(':').filter(|b| !b.is_empty()).map(String::from));
+  }
+  browsers.push(String::from("xdg-open"));
+  browsers.push(String::from("open"));
+
+  for browser in browsers {
+    // browsers can have arguments, e.g. "firefox --new-window"
+    let launched = match command::build(&browser, &[url.to_string()], config) {
+      Ok(exec) => exec,
+      Err(_) => continue
+    };
+    let launched = launched
+      .stdout(NullFile)
+      .stderr(NullFile)
+      .detached()
+      .popen();
+    // a browser may keep running, which is fine, but if it quits straight away it has to be successful
+    if let Ok(mut p) = launched {
+      match p.wait_timeout(Duration::from_secs(1)) {
+        Ok(Some(status)) if !status.success() => continue,
+        Ok(_) => return true,
+        Err(_) => continue
+      }
+    }
+  }
+  false
+}
+
+// if we're on a remote machine, explain how to see the site from the local one
+fn ssh_forwarding_help(url: &str) {
+  let connection = match env::var("SSH_CONNECTION") {
+    Ok(c) => c,
+    Err(_) => return
+  };
+  // SSH_CONNECTION is "client_ip client_port server_ip server_port"
+  let server = connection.split_whitespace().nth(2).unwrap_or("your-server");
+  let user = env::var("USER").unwrap_or_default();
+  let login = if user.is_empty() { server.to_string() } else { [&user, "@", server].concat() };
+  let port = url.rsplit(':').next()
+    .map(|p| p.trim_end_matches('/').split('/').next().unwrap_or(""))
+    .filter(|p| p.parse::<u16>().is_ok())
+    .unwrap_or("80");
+  println!("👀 You're connected over SSH. To see your site from your own machine, run this there and then visit http://localhost:{}", port);
+  println!("  ssh -N -L {}:localhost:{} {}", port, port, login);
+}
+
+// open the site in a browser, or tell the user where it is
+fn show_site(config: &Config, url: &str, no_browser: bool) {
+  if no_browser {
+    println!("Your site is running at {}", url);
+    ssh_forwarding_help(url);
+  } else if open_browser(config, url) {
+    println!("Loading site locally in your browser...");
+  } else {
+    println!("😬 Couldn't open a browser. Your site is running at {}", url);
+    ssh_forwarding_help(url);
+  }
+}
+
+fn wait_for_enter() {
+  color!(bold);
+  color!(green);
+  print!("Press Return/Enter to finish testing");
+  color!(gray);
+  close_color!(bold);
+  io::stdout().flush().unwrap();
+  let mut input = String::new();
+  io::stdin().read_line(&mut input).unwrap();
+}
+
+fn test(config: &Config, no_browser: bool) -> subprocess::Result<bool> {
+
+  // nothing to run, so we serve the output directory ourselves
+  let ssg_can_serve = ["hugo", "eleventy"].contains(&config.ssg_type.as_str());
+  if config.commands.test.is_empty() && !ssg_can_serve {
+    if group::in_use(&config.serve_address) {
+      port_in_use(&config.serve_address);
+      return Ok(false)
+    }
+    let server = serve::start(config)?;
+    show_site(config, &server.url, no_browser);
+    wait_for_enter();
+    server.stop();
+    println!("goodbye");
+    color!(reset);
+    return Ok(true)
+  }
+
   let wd = shellexpand::full(&config.workdir).expect("Error reading working directory").to_string();
-  // string needs to be an Option<OsString> for Popen Config
-  let os_string: Option<OsString> = Some(OsString::from(&wd));
 
   // deal with possible empty config values
   // and set defaults depending on ssg_type
 
   let ct = &config.commands.test;
   let test_url = &config.test_url;
-  let ssg = &config.ssg_type.as_str();
+  let ssg = config.ssg_type.as_str();
 
-  let commands;
-  if ct == &String::from("") {
-    commands = match ssg {
-      &"hugo" => "hugo server -w --quiet",
+  let commands = if ct.is_empty() {
+    match ssg {
+      "hugo" => "hugo server -w --quiet",
       _ => "eleventy --input=input --quiet --serve"
     }
   } else {
-    commands = ct.as_str();
-  }
+    ct.as_str()
+  };
 
-  let url;
-  if test_url == &String::from("") {
-    url = match ssg {
-      &"hugo" => "http://localhost:1313",
+  let url = if test_url.is_empty() {
+    match ssg {
+      "hugo" => "http://localhost:1313",
       _ => "http://localhost:8080"
     }
   } else {
-    url = test_url.as_str();
+    test_url.as_str()
+  };
+
+  if group::in_use(url) {
+    port_in_use(url);
+    return Ok(false)
   }
 
-  // use the original string, split on whitespace to create iterator
-  let a = commands.split_whitespace();
-  // collect into Vec
-  let b: Vec<&str> = a.collect();
-  let mut running_session = Popen::create(&b, PopenConfig {
-    cwd: os_string,
+  // server output goes to a log file instead of the terminal
+  // so we can show it if the server doesn't start
+  let log_path = env::temp_dir().join("letters-test.log");
+  let log = fs::File::create(&log_path)?;
+  let parsed = command::parse(commands, &[], config)?;
+  let vars: HashMap<OsString, OsString> = env::vars_os()
+    .chain(parsed.env.into_iter().map(|(k, v)| (k.into(), v.into())))
+    .collect();
+  // its own process group, so we can stop everything the command starts
+  let mut running_session = Popen::create(&parsed.argv, PopenConfig {
+    cwd: Some(wd.into()),
+    env: Some(vars.into_iter().collect()),
+    stdout: Redirection::File(log),
+    stderr: Redirection::Merge,
     detached: true,
+    #[cfg(unix)]
+    setpgid: true,
     ..Default::default()
   })?;
-  println!("Loading site locally in your browser, this may take a few seconds...");
-  // wait on process to give it time to load
-  let _waiting = running_session.wait_timeout(Duration::new(5,0));
-  // open the browser to the local url
-  Command::new("open")
-    .arg(url)
-    .output()
-    .expect("Failed to open test_url");
+  if let Some(pid) = running_session.pid() {
+    group::track(pid);
+  }
+  group::handle_signals();
+  println!("Waiting for your site to load at {}, this may take a few seconds...", url);
+  if let Err(e) = wait_for_server(&mut running_session, url, Duration::from_secs(config.test_timeout)) {
+    eprintln!("😭 {}", e);
+    show_log(&log_path);
+    group::stop_tracked();
+    return Ok(false)
+  }
+  println!("Server output is logged to {}", log_path.display());
+  show_site(config, url, no_browser);
 
   // Give user option to close local webserver process
   // We do this because it's running as a detached session
   // So it has to be terminated by the script rather than simply in the terminal
-  color!(bold);
-  color!(green);
-  print!("Press Return/Enter to finish testing");
-  color!(gray);
-  close_color!(bold);
-  io::stdout().flush().unwrap();
-  let mut input = String::new();
-  // when user hits Enter, terminate session
-  io::stdin().read_line(&mut input).unwrap();
-  running_session.terminate().unwrap();
+  // Ctrl-C stops it too
+  wait_for_enter();
+  // when user hits Enter, stop the server and everything it started
+  group::stop_tracked();
   println!("goodbye");
   color!(reset);
-  Ok(()) // return Ok to original function call
+  Ok(true) // return Ok to original function call
+}
+
+fn port_in_use(url: &str) {
+  eprintln!("😭 Something is already running at {}", url);
+  eprintln!("If it's a test server left over from before, stop it with 'letters test --stop'");
 }
 
 fn unsplash(config: &Config, topic: &str) -> (String, String) {
@@ -298,14 +1029,14 @@ fn unsplash(config: &Config, topic: &str) -> (String, String) {
     let mut _photo = String::from("");
     let mut _description = String::from("");
 
-    if json["urls"]["small"].to_string() != "null" {
+    if !json["urls"]["small"].is_null() {
       // if there's a result use that
       _description = json["description"].to_string();
       _photo = json["urls"]["small"].to_string();
     } else {
       // else run unsplash query without topic
-      let q = format!("https://api.unsplash.com/photos/random");
-      let r = ureq::get(&q)
+      let q = "https://api.unsplash.com/photos/random";
+      let r = ureq::get(q)
       .set("Authorization", &auth)
       .call();
 
@@ -321,6 +1052,9 @@ fn unsplash(config: &Config, topic: &str) -> (String, String) {
 
 fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
 
+    if !hooks::run(config, "pre_write", &[])? {
+      return Ok(false)
+    }
     colol::init();
     // Title
     color!(bold);
@@ -364,8 +1098,8 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     io::stdin().read_line(&mut given_tags).unwrap();
     color!(reset);
 
-    if given_tags.trim().len() > 0 {
-      vec = given_tags.split(",").collect(); // collect all tags if there are any
+    if !given_tags.trim().is_empty() {
+      vec = given_tags.split(',').collect(); // collect all tags if there are any
     }
 
     if &config.ssg_type == "eleventy" {
@@ -410,9 +1144,9 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     contents.push_str("author: ");
     contents.push_str(&config.author);
     contents.push_str("\ntags: ");
-    contents.push_str("[");
+    contents.push('[');
     contents.push_str(&tags);
-    contents.push_str("]");
+    contents.push(']');
     contents.push_str("\nsummary: ");
     contents.push_str(&summary);
     contents.push_str("date: ");
@@ -441,7 +1175,15 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     let dir = config.input.as_str(); // blog input directory for markdown file
     let directory = shellexpand::full(dir).expect("Error reading input directory").to_string(); // expand to full path
     let filepath = Path::new(&directory).join(&hyphenated); // add filename to path
-    fs::write(filepath, contents).expect("Error writing out file."); // write out file
+    fs::write(&filepath, contents).expect("Error writing out file."); // write out file
+    let context = [
+      ("LETTERS_POST", filepath.to_string_lossy().to_string()),
+      ("LETTERS_SLUG", hyphenated.trim_end_matches(".md").to_string()),
+      ("LETTERS_TITLE", title.trim().to_string())
+    ];
+    if !hooks::run(config, "post_write", &context)? {
+      return Ok(false)
+    }
     // open file
     let fp = Path::new(&directory).join(&hyphenated);
     let exit_status = Exec::cmd("open").arg(fp).join()?;
@@ -455,6 +1197,16 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     }
   }
 
+  // the title and link of the newest post in the RSS file, if there is one
+  fn newest_post(config: &Config) -> Option<(String, String)> {
+    let rss = shellexpand::full(&config.rss_file).ok()?.to_string();
+    let file = fs::File::open(rss).ok()?;
+    let channel = Channel::read_from(BufReader::new(file)).ok()?;
+    // the last item is the newest, the same as for get_social_post
+    let last = channel.items.last()?;
+    Some((last.title()?.to_string(), last.link().unwrap_or("").to_string()))
+  }
+
   fn get_social_post(config: &Config, msg: Option<&str>) -> Result<String, rss::Error> {
     // Get the last item from the RSS file
     // Normally this will be the post you just wrote
@@ -472,7 +1224,7 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     // otherwise we fall back to the title of the post
     let text = msg.unwrap_or(title);
     post.push_str(text);
-    post.push_str("\n");
+    post.push('\n');
     post.push_str(link);
     // return the text of the post for use
     Ok(post)
@@ -541,53 +1293,84 @@ fn write(config: &Config, no_image: bool) -> subprocess::Result<bool> {
     .send()
   }
 
-fn check_status(res: reqwest::blocking::Response, platform: String) {
+// returns whether the post was sent
+fn check_status(res: reqwest::blocking::Response, platform: String) -> bool {
   if res.status() == 200 {
     if platform == "twitter" {
       println!("🐦 tweeted!");
     } else {
       println!("📣 tooted!");
     }
-
+    // remember where it went in the deploy history
+    let post: serde_json::Value = serde_json::from_str(&res.text().unwrap_or_default()).unwrap_or_default();
+    let url = if platform == "twitter" {
+      post["id_str"].as_str().map(|id| ["https://twitter.com/i/web/status/", id].concat())
+    } else {
+      post["url"].as_str().map(String::from)
+    };
+    if let Some(url) = url {
+      if let Err(e) = deploys::add_social(&platform, &url) {
+        eprintln!("😬 Couldn't add your {} post to your deploy history: {}", platform, e);
+      }
+    }
+    true
   } else {
     println!("😭 {} returned error code {}", platform, res.status());
+    false
   }
 }
 
-fn publish_to_social(matches: ArgMatches, config: Config) {
-
-  println!("Published! 🚀");
+// returns false if any of the posts failed
+fn announce(config: &Config, send_toot: bool, send_tweet: bool, message: Option<&str>) -> bool {
+  let mut sent = true;
 
-  if matches.is_present("toot") {
-    let res = toot(&config, matches.value_of("message"));
-    match res {
+  if send_toot {
+    let res = toot(config, message);
+    sent &= match res {
       Ok(res) => check_status(res, String::from("mastodon")),
-      Err(err) => println!("😭 error tooting: {:#?}", err)
+      Err(err) => {
+        println!("😭 error tooting: {:#?}", err);
+        false
+      }
     }
   }
 
-  if matches.is_present("tweet") {
-    let res = tweet(&config, matches.value_of("message"));
-    match res {
+  if send_tweet {
+    let res = tweet(config, message);
+    sent &= match res {
       Ok(res) => check_status(res, String::from("twitter")),
-      Err(err) => println!("😭 error tweeting: {:#?}", err)
+      Err(err) => {
+        println!("😭 error tweeting: {:#?}", err);
+        false
+      }
     }
   }
 
+  sent
+}
+
+fn publish_to_social(matches: ArgMatches, config: Config) {
+
+  println!("Published! 🚀");
+
+  announce(&config, matches.is_present("toot"), matches.is_present("tweet"), matches.value_of("message"));
+
 }
 
 fn does_config_exist() -> std::result::Result<String, std::io::Error>{
   // read config file and return result
   let fp = shellexpand::full("~/.letters.toml").unwrap();
-  let s = fs::read_to_string(&fp.into_owned())?;
+  let s = fs::read_to_string(fp.into_owned())?;
   Ok(s)
 }
 
 fn first_time_setup() {
-  println!("You need a config file to do anything!\nLet's set one up...");
-  // wait 3 seconds so the user reads the message
-  sleep(Duration::new(3,0));
-  setup()
+  if std::env::args().any(|a| a == "--edit") {
+    setup::edit()
+  } else {
+    println!("You need a config file to do anything!\nLet's set one up...");
+    setup::wizard()
+  }
 }
 
 fn run(s: String) {
@@ -600,7 +1383,59 @@ fn run(s: String) {
       .arg(Arg::with_name("ACTION")
           .help("Action to perform")
           .required(true)
-          .possible_values(&["setup", "process", "publish", "test", "write"])
+          .possible_values(&["setup", "check", "deploys", "lint", "process", "publish", "rollback", "ship", "sitemap", "test", "watch", "write"])
+          )
+      .arg(Arg::with_name("WHICH")
+          .help("The release to go back to with 'rollback', which defaults to the one before the current release, or the deploy to show with 'deploys'")
+          .index(2)
+          )
+      .arg(Arg::with_name("dry-run")
+          .help("Show what 'publish' or 'ship' would change on the server, without changing anything")
+          .long("dry-run")
+          )
+      .arg(Arg::with_name("edit")
+          .help("Open the config file for editing instead of running the setup wizard")
+          .long("edit")
+          .required(false)
+          .takes_value(false)
+          )
+      .arg(Arg::with_name("verbose")
+          .help("Show all output from your static site generator while processing")
+          .long("verbose")
+          .short("v")
+          .required(false)
+          .takes_value(false)
+          )
+      .arg(Arg::with_name("force")
+          .help("Process even if nothing has changed")
+          .long("force")
+          .short("f")
+          .required(false)
+          .takes_value(false)
+          )
+      .arg(Arg::with_name("json")
+          .help("Show problems found by 'lint', or your 'deploys', as JSON")
+          .long("json")
+          )
+      .arg(Arg::with_name("no-browser")
+          .help("Don't open a browser when testing, just show the URL")
+          .long("no-browser")
+          .required(false)
+          .takes_value(false)
+          )
+      .arg(Arg::with_name("target")
+          .help("Publish to one of the [[targets]] in your config file, instead of default_target")
+          .long("target")
+          .takes_value(true)
+          )
+      .arg(Arg::with_name("yes")
+          .help("Publish without asking first, even if lots of files would be deleted from the server")
+          .long("yes")
+          .short("y")
+          )
+      .arg(Arg::with_name("stop")
+          .help("Stop a test server left running by a previous 'letters test'")
+          .long("stop")
           )
       .arg(Arg::with_name("no-image")
           .help("Don't get an image from Unsplash")
@@ -631,8 +1466,21 @@ fn run(s: String) {
           )
       .get_matches();
 
+  let config = match select_target(config, matches.value_of("target")) {
+    Ok(mut c) => {
+      c.yes = matches.is_present("yes");
+      c
+    },
+    Err(e) => {
+      eprintln!("😭 {}", e);
+      return
+    }
+  };
+
   // if toot or tweet...
-  if matches.is_present("toot") | matches.is_present("tweet") {
+  // ship does its own tooting and tweeting
+  let shipping = matches.value_of("ACTION") == Some("ship");
+  if (matches.is_present("toot") | matches.is_present("tweet")) && !matches.is_present("dry-run") && !shipping {
       if matches.value_of("ACTION").unwrap() == "publish" {
         match publish(&config) {
           // We do it like this so that the social post only gets published if the blog post is successfully published first
@@ -640,26 +1488,62 @@ fn run(s: String) {
           Err(err) => eprintln!("'publish' command failed!\nCheck your config file is correct.\nError: {}", err)
         }
       } else {
-        println!("--toot and --tweet can only be used with publish")
+        println!("--toot and --tweet can only be used with publish and ship")
       }
   } else {
     let action = matches.value_of("ACTION").unwrap();
     match action {
-      "setup" => setup(),
-      "process" => match process(&config) {
-        Ok(x) => if x {()} else {eprintln!("Uh oh, the 'process' command failed!\nCheck your config file is correct.")},
+      "setup" => if matches.is_present("edit") { setup::edit() } else { setup::wizard() },
+      "check" => match check::check(&config) {
+        Ok(x) => if !x {std::process::exit(1)},
+        Err(err) => eprintln!("'check' command failed!\nCheck your config file is correct.\nError: {}", err)
+      },
+      "deploys" => match deploys::deploys(matches.value_of("WHICH"), matches.is_present("json")) {
+        Ok(_x) => (),
+        Err(err) => eprintln!("'deploys' command failed!\nError: {}", err)
+      },
+      // exits with an error if there are problems, so it can be used in CI
+      "lint" => match lint::lint(&config, matches.is_present("json")) {
+        Ok(x) => if !x {std::process::exit(1)},
+        Err(err) => eprintln!("'lint' command failed!\nCheck your config file is correct.\nError: {}", err)
+      },
+      "process" => match process(&config, matches.is_present("verbose"), matches.is_present("force")) {
+        Ok(x) => if !x {eprintln!("Uh oh, the 'process' command failed!\nCheck your config file is correct.")},
         Err(err) => eprintln!("'process' command failed!\nCheck your config file is correct.\nError: {}", err)
       },
+      "publish" if matches.is_present("dry-run") => match dry_run(&config) {
+        Ok(x) => if !x {eprintln!("Uh oh, the 'publish' dry run failed!\nCheck your config file is correct.")},
+        Err(err) => eprintln!("'publish' dry run failed!\nCheck your config file is correct.\nError: {}", err)
+      },
       "publish" => match publish(&config) {
         Ok(x) => if x {println!("Published! 🚀")} else {eprintln!("Uh oh, the 'publish' command failed!\nCheck your config file is correct.")},
         Err(err) => eprintln!("'publish' command failed!\nCheck your config file is correct.\nError: {}", err)
       },
-      "test" => match test(&config) {
-        Ok(_v) => (),
+      "rollback" => match releases::rollback(&config, matches.value_of("WHICH")) {
+        Ok(x) => if !x {eprintln!("Uh oh, the 'rollback' command failed!\nCheck your config file is correct.")},
+        Err(err) => eprintln!("'rollback' command failed!\nCheck your config file is correct.\nError: {}", err)
+      },
+      // exits with an error if any step fails, so it can be used in CI
+      "ship" => if !ship::ship(&config, &matches) {std::process::exit(1)},
+      "sitemap" => match sitemap::sitemap(&config) {
+        Ok(_x) => (),
+        Err(err) => eprintln!("'sitemap' command failed!\nCheck your config file is correct.\nError: {}", err)
+      },
+      "test" if matches.is_present("stop") => if group::stop_leftover() {
+        println!("Stopped the test server");
+      } else {
+        println!("No test server is running");
+      },
+      "test" => match test(&config, matches.is_present("no-browser")) {
+        Ok(x) => if !x {eprintln!("Uh oh, the 'test' command failed!\nCheck your config file is correct.")},
         Err(err) => eprintln!("'test' command failed!\nCheck your config file is correct.\nError: {}", err)
       },
+      "watch" => match watch::watch(&config, matches.is_present("verbose")) {
+        Ok(_v) => (),
+        Err(err) => eprintln!("'watch' command failed!\nCheck your config file is correct.\nError: {}", err)
+      },
       "write" => match write(&config, matches.is_present("no-image")) {
-        Ok(x) => if x {()} else {eprintln!("Uh oh, the 'write' command failed!\nCheck your config file is correct.")},
+        Ok(x) => if !x {eprintln!("Uh oh, the 'write' command failed!\nCheck your config file is correct.")},
         Err(err) => eprintln!("'write' command failed!\nCheck your config file is correct.\nError: {}", err)
       }
       ,
@@ -0,0 +1,94 @@
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// A manifest is the SHA-256 hash of every file under a set of paths
+// Comparing two manifests tells us whether anything changed between them
+
+pub type Manifest = BTreeMap<String, String>;
+
+pub fn hash_file(path: &Path) -> io::Result<String> {
+  let contents = fs::read(path)?;
+  Ok(format!("{:x}", Sha256::digest(&contents)))
+}
+
+fn add(path: &Path, manifest: &mut Manifest) -> io::Result<()> {
+  if path.is_dir() {
+    for entry in fs::read_dir(path)? {
+      add(&entry?.path(), manifest)?;
+    }
+  } else if path.is_file() {
+    manifest.insert(path.to_string_lossy().to_string(), hash_file(path)?);
+  }
+  Ok(())
+}
+
+// paths can be files or directories, paths that don't exist are ignored
+pub fn build(paths: &[PathBuf]) -> io::Result<Manifest> {
+  let mut manifest = Manifest::new();
+  for path in paths {
+    add(path, &mut manifest)?;
+  }
+  Ok(manifest)
+}
+
+// one "hash<TAB>path" per line
+pub fn load(file: &Path) -> Manifest {
+  fs::read_to_string(file)
+    .unwrap_or_default()
+    .lines()
+    .filter_map(|l| l.split_once('\t'))
+    .map(|(hash, path)| (path.to_string(), hash.to_string()))
+    .collect()
+}
+
+pub fn save(file: &Path, manifest: &Manifest) -> io::Result<()> {
+  let lines: Vec<String> = manifest.iter()
+    .map(|(path, hash)| [hash.as_str(), "\t", path.as_str()].concat())
+    .collect();
+  fs::write(file, lines.join("\n"))
+}
+
+// number of files added, changed or removed between two manifests
+pub fn changes(old: &Manifest, new: &Manifest) -> usize {
+  let changed = new.iter().filter(|(path, hash)| old.get(*path) != Some(hash)).count();
+  let removed = old.keys().filter(|path| !new.contains_key(*path)).count();
+  changed + removed
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn manifest(files: &[(&str, &str)]) -> Manifest {
+    files.iter().map(|(path, hash)| (path.to_string(), hash.to_string())).collect()
+  }
+
+  #[test]
+  fn no_changes() {
+    let m = manifest(&[("a.md", "1"), ("b.md", "2")]);
+    assert_eq!(changes(&m, &m.clone()), 0);
+    assert_eq!(changes(&Manifest::new(), &Manifest::new()), 0);
+  }
+
+  #[test]
+  fn counts_added_changed_and_removed_files() {
+    let old = manifest(&[("a.md", "1"), ("b.md", "2"), ("c.md", "3")]);
+    let new = manifest(&[("a.md", "1"), ("b.md", "changed"), ("d.md", "4")]);
+    assert_eq!(changes(&old, &new), 3);
+    // everything is new the first time
+    assert_eq!(changes(&Manifest::new(), &new), 3);
+  }
+
+  #[test]
+  fn saves_and_loads() {
+    let file = std::env::temp_dir().join(format!("letters-manifest-{}", std::process::id()));
+    let m = manifest(&[("/blog/a b.md", "1"), ("/blog/c.md", "2")]);
+    save(&file, &m).unwrap();
+    assert_eq!(load(&file), m);
+    fs::remove_file(&file).unwrap();
+    assert!(load(&file).is_empty());
+  }
+}
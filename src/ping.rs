@@ -0,0 +1,114 @@
+use crate::changes::Changes;
+use crate::url::page_url;
+use crate::{newest_post, Config};
+use std::path::Path;
+use std::time::Duration;
+
+// Lets the rest of the web know about new posts after publishing, from the [ping] table
+// A WebSub hub is told your feed has changed, so feed readers subscribed through it update straight away
+// Search engines that use IndexNow (Bing, Yandex, Seznam etc) are sent the URLs of the pages that changed
+
+// the feed's URL, from feed_url or where rss_file is in output
+fn feed_url(config: &Config) -> Option<String> {
+  if !config.ping.feed_url.is_empty() {
+    return Some(config.ping.feed_url.clone())
+  }
+  let output = shellexpand::full(&config.output).ok()?.to_string();
+  let rss = shellexpand::full(&config.rss_file).ok()?.to_string();
+  let relative = match Path::new(&rss).strip_prefix(&output) {
+    Ok(relative) => relative.to_string_lossy().to_string(),
+    // e.g. rss_file = "feed.xml" is the feed.xml in output
+    Err(_) if Path::new(&rss).is_relative() => rss.trim_start_matches("./").to_string(),
+    Err(_) => return None
+  };
+  if config.site_url.is_empty() || relative.is_empty() {
+    return None
+  }
+  Some(page_url(&config.site_url, &relative))
+}
+
+fn websub(client: &reqwest::blocking::Client, config: &Config) -> Result<String, String> {
+  let feed = feed_url(config).ok_or("set feed_url in [ping], or site_url and an rss_file inside output, to use WebSub")?;
+  let response = client.post(&config.ping.websub_hub)
+    .form(&[("hub.mode", "publish"), ("hub.url", feed.as_str())])
+    .send()
+    .map_err(|e| e.to_string())?;
+  if response.status().is_success() {
+    Ok(feed)
+  } else {
+    Err(format!("the hub returned {}", response.status()))
+  }
+}
+
+fn indexnow(client: &reqwest::blocking::Client, config: &Config, urls: &[String]) -> Result<(), String> {
+  let host = config.site_url.split_once("://").map(|(_, rest)| rest).unwrap_or(&config.site_url);
+  let host = host.split('/').next().unwrap_or("");
+  if host.is_empty() {
+    return Err(String::from("set site_url to use IndexNow"))
+  }
+  let body = serde_json::json!({ "host": host, "key": config.ping.indexnow_key, "urlList": urls });
+  let response = client.post(&config.ping.indexnow_endpoint)
+    .header("Content-Type", "application/json; charset=utf-8")
+    .body(body.to_string())
+    .send()
+    .map_err(|e| e.to_string())?;
+  if response.status().is_success() {
+    Ok(())
+  } else {
+    Err(format!("{} returned {}", config.ping.indexnow_endpoint, response.status()))
+  }
+}
+
+// 'changes' is what was just published, or None if we don't know
+pub fn ping(config: &Config, changes: Option<&Changes>) {
+  let ping = &config.ping;
+  if (ping.websub_hub.is_empty() && ping.indexnow_key.is_empty()) || changes.map(|c| c.is_empty()).unwrap_or(false) {
+    return
+  }
+  // the newest post is what get_social_post() would toot or tweet about,
+  // but it's only sent if its page changed, or if we don't know what changed
+  let newest = newest_post(config);
+  let mut urls: Vec<String> = match changes {
+    Some(c) => c.added.iter().chain(c.updated.iter()).chain(c.deleted.iter())
+      .filter(|p| p.ends_with(".html"))
+      .map(|p| page_url(&config.site_url, p))
+      .collect(),
+    None => Vec::new()
+  };
+  let newest = newest.filter(|(_, link)| !link.is_empty() && (changes.is_none() || urls.contains(link)));
+  if let Some((_, link)) = &newest {
+    if !urls.contains(link) {
+      urls.push(link.clone());
+    }
+  }
+  let feed_changed = match changes {
+    Some(c) => {
+      let feed = feed_url(config);
+      c.added.iter().chain(c.updated.iter()).any(|p| Some(page_url(&config.site_url, p)) == feed)
+    },
+    None => true
+  };
+
+  let client = match reqwest::blocking::Client::builder().timeout(Duration::from_secs(15)).build() {
+    Ok(c) => c,
+    Err(e) => {
+      eprintln!("😬 Couldn't ping anyone: {}", e);
+      return
+    }
+  };
+  if !ping.websub_hub.is_empty() && feed_changed {
+    match websub(&client, config) {
+      Ok(feed) => println!("📡 Told {} that {} has changed", ping.websub_hub, feed),
+      Err(e) => eprintln!("😬 Couldn't ping the WebSub hub: {}", e)
+    }
+  }
+  if !ping.indexnow_key.is_empty() && !urls.is_empty() {
+    match indexnow(&client, config, &urls) {
+      Ok(()) => match &newest {
+        Some((title, _)) => println!("🔎 Sent {} URL{} to IndexNow, including \"{}\"", urls.len(), if urls.len() == 1 { "" } else { "s" }, title),
+        None => println!("🔎 Sent {} URL{} to IndexNow", urls.len(), if urls.len() == 1 { "" } else { "s" })
+      },
+      Err(e) => eprintln!("😬 Couldn't submit to IndexNow: {}", e)
+    }
+  }
+}
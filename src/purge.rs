@@ -0,0 +1,157 @@
+use crate::changes::Changes;
+use crate::url::{encode, page_url};
+use crate::{builtin, Config};
+use reqwest::blocking::RequestBuilder;
+use reqwest::Method;
+use std::env;
+use std::io;
+use std::time::Duration;
+
+// Tells your CDN to forget old copies of your site after publishing, from the [purge] table
+// Either everything is purged, or just the URLs of the files that changed
+// Cloudflare, Fastly and Bunny are built in, anything else can be done with a webhook
+
+// Cloudflare only takes this many URLs at a time
+const CLOUDFLARE_BATCH: usize = 30;
+
+// the URLs a changed file is served at, e.g. posts/one/index.html is also posts/one/
+fn urls(site_url: &str, path: &str) -> Vec<String> {
+  let file = [site_url.trim_end_matches('/'), "/", &encode(path, true)].concat();
+  let page = page_url(site_url, path);
+  if page == file { vec![file] } else { vec![file, page] }
+}
+
+fn token(config: &Config, var: &str) -> io::Result<String> {
+  let token = if config.purge.token.is_empty() { env::var(var).unwrap_or_default() } else { config.purge.token.clone() };
+  if token.is_empty() {
+    return Err(io::Error::other(format!("set 'token' in [purge], or {}, to purge {}", var, config.purge.provider)))
+  }
+  Ok(token)
+}
+
+fn zone(config: &Config) -> io::Result<&str> {
+  if config.purge.zone.is_empty() {
+    return Err(io::Error::other(format!("set 'zone' in [purge] to purge {}", config.purge.provider)))
+  }
+  Ok(&config.purge.zone)
+}
+
+fn send(request: RequestBuilder) -> io::Result<()> {
+  let response = request.send().map_err(|e| io::Error::other(e.to_string()))?;
+  if !response.status().is_success() {
+    let status = response.status();
+    let text = response.text().unwrap_or_default();
+    return Err(io::Error::other(format!("{} {}", status, text.trim())))
+  }
+  Ok(())
+}
+
+fn webhook(client: &reqwest::blocking::Client, config: &Config, urls: Option<&[String]>, paths: &[String]) -> io::Result<()> {
+  let purge = &config.purge;
+  if purge.url.is_empty() {
+    return Err(io::Error::other("set 'url' in [purge] to purge with a webhook"))
+  }
+  let json = |list: &[String]| serde_json::to_string(list).unwrap_or_default();
+  let values = [
+    ("urls", json(urls.unwrap_or(&[]))),
+    ("paths", json(paths)),
+    ("scope", String::from(if urls.is_some() { "changed" } else { "all" })),
+    ("site_url", config.site_url.clone())
+  ];
+  let method = Method::from_bytes(purge.method.to_uppercase().as_bytes())
+    .map_err(|_| io::Error::other(format!("'{}' isn't an HTTP method", purge.method)))?;
+  // $VARIABLES are expanded in the URL and headers, so tokens don't have to be in the config file
+  let url = shellexpand::env(&builtin::fill(&purge.url, &values)).map_err(|e| io::Error::other(e.to_string()))?.to_string();
+  let mut request = client.request(method, &url);
+  for (name, value) in &purge.headers {
+    let value = shellexpand::env(value).map_err(|e| io::Error::other(e.to_string()))?;
+    request = request.header(name.as_str(), value.as_ref());
+  }
+  if !purge.body.is_empty() {
+    request = request.body(builtin::fill(&purge.body, &values));
+  }
+  send(request)
+}
+
+fn cloudflare(client: &reqwest::blocking::Client, config: &Config, urls: Option<&[String]>) -> io::Result<()> {
+  let endpoint = format!("https://api.cloudflare.com/client/v4/zones/{}/purge_cache", zone(config)?);
+  let token = token(config, "CLOUDFLARE_API_TOKEN")?;
+  let bodies = match urls {
+    None => vec![serde_json::json!({ "purge_everything": true })],
+    Some(urls) => urls.chunks(CLOUDFLARE_BATCH).map(|batch| serde_json::json!({ "files": batch })).collect()
+  };
+  for body in bodies {
+    send(client.post(&endpoint).bearer_auth(&token).header("Content-Type", "application/json").body(body.to_string()))?;
+  }
+  Ok(())
+}
+
+fn fastly(client: &reqwest::blocking::Client, config: &Config, urls: Option<&[String]>) -> io::Result<()> {
+  let token = token(config, "FASTLY_API_TOKEN")?;
+  match urls {
+    None => send(client.post(format!("https://api.fastly.com/service/{}/purge_all", zone(config)?)).header("Fastly-Key", &token)),
+    Some(urls) => {
+      for url in urls {
+        let without_scheme = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+        send(client.post(["https://api.fastly.com/purge/", without_scheme].concat()).header("Fastly-Key", &token))?;
+      }
+      Ok(())
+    }
+  }
+}
+
+fn bunny(client: &reqwest::blocking::Client, config: &Config, urls: Option<&[String]>) -> io::Result<()> {
+  let token = token(config, "BUNNY_API_KEY")?;
+  match urls {
+    None => send(client.post(format!("https://api.bunny.net/pullzone/{}/purgeCache", zone(config)?)).header("AccessKey", &token)),
+    Some(urls) => {
+      for url in urls {
+        send(client.post("https://api.bunny.net/purge").query(&[("url", url)]).header("AccessKey", &token))?;
+      }
+      Ok(())
+    }
+  }
+}
+
+// 'changes' is None if we don't know what changed, in which case everything is purged
+pub fn purge(config: &Config, changes: Option<&Changes>) -> io::Result<()> {
+  let purge = &config.purge;
+  if purge.provider.is_empty() {
+    return Ok(())
+  }
+  if purge.scope != "changed" && purge.scope != "all" {
+    return Err(io::Error::other(format!("'{}' isn't a purge scope lette.rs knows about. Use \"changed\" or \"all\"", purge.scope)))
+  }
+  let paths: Vec<String> = match changes {
+    Some(c) if purge.scope != "all" => c.updated.iter().chain(c.deleted.iter()).chain(c.added.iter()).cloned().collect(),
+    _ => Vec::new()
+  };
+  let everything = purge.scope == "all" || changes.is_none();
+  if !everything && paths.is_empty() {
+    return Ok(())
+  }
+  if !everything && config.site_url.is_empty() {
+    return Err(io::Error::other("set site_url so lette.rs knows which URLs to purge, or set scope = \"all\" in [purge]"))
+  }
+  let list: Vec<String> = paths.iter().flat_map(|p| urls(&config.site_url, p)).collect();
+  let urls = if everything { None } else { Some(list.as_slice()) };
+
+  let client = reqwest::blocking::Client::builder()
+    .timeout(Duration::from_secs(30))
+    .build()
+    .map_err(|e| io::Error::other(e.to_string()))?;
+  match purge.provider.as_str() {
+    "webhook" => webhook(&client, config, urls, &paths)?,
+    "cloudflare" => cloudflare(&client, config, urls)?,
+    "fastly" => fastly(&client, config, urls)?,
+    "bunny" => bunny(&client, config, urls)?,
+    provider => return Err(io::Error::other(format!(
+      "'{}' isn't a purge provider lette.rs knows about. Use \"cloudflare\", \"fastly\", \"bunny\" or \"webhook\"", provider
+    )))
+  }
+  match urls {
+    None => println!("🧹 Purged everything from {}", purge.provider),
+    Some(urls) => println!("🧹 Purged {} URL{} from {}", urls.len(), if urls.len() == 1 { "" } else { "s" }, purge.provider)
+  }
+  Ok(())
+}
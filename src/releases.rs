@@ -0,0 +1,133 @@
+use crate::{has_server, purge, Config};
+use chrono::Utc;
+use std::io;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+// Atomic releases: each publish goes into remote_dir/releases/<timestamp>, and then the
+// remote_dir/current symlink is switched over to it in one step
+// Visitors never see a half-updated site, and rolling back is just pointing the symlink at an older release
+
+pub fn remote_dir(config: &Config) -> String {
+  shellexpand::full(&config.remote_dir).expect("Error reading remote directory").trim_end_matches('/').to_string()
+}
+
+// where rsync should compare against when checking what will change
+pub fn current_dir(config: &Config) -> String {
+  [remote_dir(config).as_str(), "/current/"].concat()
+}
+
+pub fn release_dir(config: &Config, name: &str) -> String {
+  [remote_dir(config).as_str(), "/releases/", name, "/"].concat()
+}
+
+// a new release name, these sort oldest to newest
+pub fn new_name() -> String {
+  Utc::now().format("%Y%m%d%H%M%S").to_string()
+}
+
+// runs a shell command on the server, with the same ssh command rsync uses
+fn ssh(config: &Config, command: &str) -> io::Result<String> {
+  let ssh = if config.rsync.ssh.is_empty() {
+    vec![String::from("ssh")]
+  } else {
+    shell_words::split(&config.rsync.ssh).map_err(|e| io::Error::other(format!("couldn't read the ssh command '{}': {}", config.rsync.ssh, e)))?
+  };
+  let capture = Exec::cmd(&ssh[0])
+    .args(&ssh[1..])
+    .arg(&config.server_name)
+    .arg(command)
+    .env_extend(&config.env.iter().collect::<Vec<_>>())
+    .stdout(Redirection::Pipe)
+    .capture()
+    .map_err(|e| io::Error::other(e.to_string()))?;
+  match capture.exit_status {
+    ExitStatus::Exited(0) => Ok(capture.stdout_str()),
+    status => Err(io::Error::other(format!("'{}' failed on {} ({:?})", command, config.server_name, status)))
+  }
+}
+
+// every release on the server, oldest first, and the one 'current' points to
+pub fn list(config: &Config) -> io::Result<(Vec<String>, Option<String>)> {
+  let remote = shell_words::quote(&remote_dir(config)).to_string();
+  let said = ssh(config, &format!("mkdir -p {0}/releases && ls -1 {0}/releases && echo '::current::' && (readlink {0}/current || true)", remote))?;
+  let (listed, current) = said.split_once("::current::").unwrap_or((&said, ""));
+  let mut releases: Vec<String> = listed.lines().map(|l| l.trim().to_string()).filter(|l| !l.is_empty()).collect();
+  releases.sort();
+  let current = current.trim().rsplit('/').next().filter(|c| !c.is_empty()).map(String::from);
+  Ok((releases, current))
+}
+
+// point 'current' at a release, renaming a new symlink over the old one so there's no moment without one
+// GNU mv needs -T and BSD mv (macOS, FreeBSD) needs -h to replace the symlink rather than move into the directory it points to
+pub fn switch(config: &Config, name: &str) -> io::Result<()> {
+  let remote = shell_words::quote(&remote_dir(config)).to_string();
+  let target = shell_words::quote(&["releases/", name].concat()).to_string();
+  ssh(config, &format!(
+    "cd {} && ln -sfn {} current.tmp && {{ mv -Tf current.tmp current 2>/dev/null || mv -hf current.tmp current; }} || {{ rm -f current.tmp; exit 1; }}",
+    remote, target
+  ))?;
+  Ok(())
+}
+
+// remove all but the newest keep_releases, and never the live one
+pub fn prune(config: &Config) -> io::Result<usize> {
+  let (releases, current) = list(config)?;
+  let keep = config.keep_releases.max(1);
+  let old: Vec<String> = releases.iter()
+    .take(releases.len().saturating_sub(keep))
+    .filter(|r| Some(*r) != current.as_ref())
+    .map(|r| shell_words::quote(r).to_string())
+    .collect();
+  if !old.is_empty() {
+    let remote = shell_words::quote(&remote_dir(config)).to_string();
+    ssh(config, &format!("cd {}/releases && rm -rf -- {}", remote, old.join(" ")))?;
+  }
+  Ok(old.len())
+}
+
+fn show(releases: &[String], current: &Option<String>) {
+  for release in releases {
+    let marker = if Some(release) == current.as_ref() { " <- current" } else { "" };
+    println!("  {}{}", release, marker);
+  }
+}
+
+// go back to a particular release, or the one before the current one
+pub fn rollback(config: &Config, release: Option<&str>) -> io::Result<bool> {
+  if !config.atomic_releases {
+    eprintln!("😬 You can only roll back when you publish with atomic_releases = true");
+    return Ok(false)
+  }
+  if !has_server(config) {
+    return Ok(false)
+  }
+  let (releases, current) = list(config)?;
+  if releases.is_empty() {
+    eprintln!("😭 There are no releases on {} to roll back to", config.server_name);
+    return Ok(false)
+  }
+  let target = match release {
+    Some(r) if releases.iter().any(|x| x == r) => r.to_string(),
+    Some(r) => {
+      eprintln!("😭 There is no release called {}. These are the releases on {}:", r, config.server_name);
+      show(&releases, &current);
+      return Ok(false)
+    },
+    None => {
+      let position = current.as_ref().and_then(|c| releases.iter().position(|r| r == c)).unwrap_or(releases.len());
+      if position == 0 {
+        eprintln!("😭 There is no release before the current one. These are the releases on {}:", config.server_name);
+        show(&releases, &current);
+        return Ok(false)
+      }
+      releases[position - 1].clone()
+    }
+  };
+  switch(config, &target)?;
+  println!("⏪ {} is now serving release {}", config.server_name, target);
+  // every page might be different in the release we went back to
+  if let Err(e) = purge::purge(config, None) {
+    eprintln!("😬 Couldn't purge your CDN, it might show pages from the newer release for a while: {}", e);
+  }
+  Ok(true)
+}
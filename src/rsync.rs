@@ -0,0 +1,138 @@
+use crate::changes::Changes;
+use crate::{command, Config};
+use std::io;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+// Asks rsync what it would do, without changing anything on the server
+// This only works when the publish command is rsync, since we rely on its --itemize-changes output
+
+// --quiet would hide the list of changes
+fn unquiet(arg: &str) -> Option<String> {
+  if arg == "--quiet" || arg == "-q" {
+    return None
+  }
+  // combined short flags like -rtOq
+  if arg.starts_with('-') && !arg.starts_with("--") && arg[1..].chars().all(|c| c.is_ascii_alphabetic()) {
+    let flags: String = arg.chars().filter(|c| *c != 'q').collect();
+    return if flags == "-" { None } else { Some(flags) }
+  }
+  Some(arg.to_string())
+}
+
+// each line is like ">f+++++++++ posts/new/index.html" or "*deleting   old.html"
+fn parse(output: &str) -> Changes {
+  let mut changes = Changes::default();
+  for line in output.lines() {
+    let (code, path) = match line.split_once(' ') {
+      Some((c, p)) => (c, p.trim_start().to_string()),
+      None => continue
+    };
+    if code == "*deleting" {
+      changes.deleted.push(path);
+    } else if code.len() < 2 || code.starts_with('.') || code[1..].starts_with('d') {
+      // directories, and files that only have new permissions or times
+      continue
+    } else if code.contains("+++") {
+      changes.added.push(path);
+    } else {
+      changes.updated.push(path);
+    }
+  }
+  changes
+}
+
+fn program(path: &str) -> &str {
+  path.rsplit('/').next().unwrap_or("")
+}
+
+pub fn is_rsync(cmd: &str, config: &Config) -> bool {
+  command::parse(cmd, &[], config)
+    .map(|p| program(&p.argv[0]) == "rsync")
+    .unwrap_or(false)
+}
+
+pub fn configured(config: &Config) -> bool {
+  let r = &config.rsync;
+  !(r.exclude.is_empty() && r.chmod.is_empty() && r.ssh.is_empty() && r.flags.is_empty())
+}
+
+// the [rsync] settings as arguments, each one a single argument so nothing needs quoting
+pub fn options(config: &Config, remote: &str) -> Vec<String> {
+  let r = &config.rsync;
+  let mut options: Vec<String> = r.exclude.iter().map(|e| ["--exclude=", e].concat()).collect();
+  if !r.chmod.is_empty() {
+    options.push(["--chmod=", &r.chmod].concat());
+  }
+  if !r.ssh.is_empty() {
+    options.push(["--rsh=", &r.ssh].concat());
+  }
+  options.extend(r.flags.iter().cloned());
+  // otherwise the remote shell splits a remote_dir with spaces in it
+  if shell_words::quote(remote) != remote {
+    options.push(String::from("--protect-args"));
+  }
+  options
+}
+
+// None if the publish command isn't rsync
+pub fn preview(cmd: &str, args: &[String], config: &Config) -> io::Result<Option<Changes>> {
+  let parsed = command::parse(cmd, args, config)?;
+  if program(&parsed.argv[0]) != "rsync" {
+    return Ok(None)
+  }
+  let flags: Vec<String> = parsed.argv[1..].iter().filter_map(|a| unquiet(a)).collect();
+  let capture = Exec::cmd(&parsed.argv[0])
+    .arg("--dry-run")
+    .arg("--itemize-changes")
+    .args(&flags)
+    .env_extend(&parsed.env)
+    .stdout(Redirection::Pipe)
+    .stderr(Redirection::Merge)
+    .capture()
+    .map_err(|e| io::Error::other(e.to_string()))?;
+  match capture.exit_status {
+    ExitStatus::Exited(0) => Ok(Some(parse(&capture.stdout_str()))),
+    status => Err(io::Error::other(format!("rsync failed ({:?}):\n{}", status, capture.stdout_str().trim_end())))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn removes_quiet_flags() {
+    assert_eq!(unquiet("--quiet"), None);
+    assert_eq!(unquiet("-q"), None);
+    assert_eq!(unquiet("-rtOq").as_deref(), Some("-rtO"));
+    assert_eq!(unquiet("-qq"), None);
+    assert_eq!(unquiet("--del").as_deref(), Some("--del"));
+    assert_eq!(unquiet("--exclude=quiet").as_deref(), Some("--exclude=quiet"));
+    assert_eq!(unquiet("-e").as_deref(), Some("-e"));
+    assert_eq!(unquiet("out/").as_deref(), Some("out/"));
+  }
+
+  #[test]
+  fn parses_itemized_changes() {
+    let output = [
+      "cd+++++++++ posts/new/",
+      ">f+++++++++ posts/new/index.html",
+      ">f.st...... index.html",
+      ">f..t...... feed/rss.xml",
+      ".f...p..... about.html",
+      ".d..t...... posts/",
+      "<f+++++++++ a b/x.txt",
+      "*deleting   old/index.html",
+      "*deleting   old/"
+    ].join("\n");
+    let changes = parse(&output);
+    assert_eq!(changes.added, ["posts/new/index.html", "a b/x.txt"]);
+    assert_eq!(changes.updated, ["index.html", "feed/rss.xml"]);
+    assert_eq!(changes.deleted, ["old/index.html", "old/"]);
+  }
+
+  #[test]
+  fn nothing_to_do_is_no_changes() {
+    assert!(parse("").is_empty());
+  }
+}
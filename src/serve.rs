@@ -0,0 +1,264 @@
+use crate::{snapshot, Config};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::UNIX_EPOCH;
+use tiny_http::{Header, Method, Request, Response, StatusCode};
+
+// A small local web server for 'letters test'
+// Used when there is no 'test' command and the SSG can't serve the site itself
+
+const RELOAD_PATH: &str = "/__letters/reload";
+
+// polls the server and reloads the page when the output directory changes
+const RELOAD_SCRIPT: &str = r#"<script>
+(function () {
+  var version = null;
+  setInterval(function () {
+    fetch('/__letters/reload').then(function (r) { return r.text() }).then(function (v) {
+      if (version !== null && v !== version) { location.reload() }
+      version = v;
+    }).catch(function () {});
+  }, 1000);
+})();
+</script>"#;
+
+pub fn content_type(path: &Path) -> &'static str {
+  let ext = path.extension()
+    .and_then(|e| e.to_str())
+    .unwrap_or("")
+    .to_lowercase();
+  match ext.as_str() {
+    "html" | "htm" => "text/html; charset=utf-8",
+    "css" => "text/css; charset=utf-8",
+    "js" | "mjs" => "text/javascript; charset=utf-8",
+    "json" => "application/json",
+    "xml" | "rss" | "atom" | "xsl" => "application/xml",
+    "txt" | "md" => "text/plain; charset=utf-8",
+    "csv" => "text/csv; charset=utf-8",
+    "svg" => "image/svg+xml",
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "avif" => "image/avif",
+    "ico" => "image/x-icon",
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    "ttf" => "font/ttf",
+    "otf" => "font/otf",
+    "pdf" => "application/pdf",
+    "mp3" => "audio/mpeg",
+    "ogg" => "audio/ogg",
+    "mp4" => "video/mp4",
+    "webm" => "video/webm",
+    "wasm" => "application/wasm",
+    "webmanifest" => "application/manifest+json",
+    _ => "application/octet-stream"
+  }
+}
+
+// turn %20 etc back into characters
+pub fn decode(s: &str) -> String {
+  let bytes = s.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    if bytes[i] == b'%' && i + 2 < bytes.len() {
+      let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+      if let Ok(b) = u8::from_str_radix(hex, 16) {
+        out.push(b);
+        i += 3;
+        continue
+      }
+    }
+    out.push(bytes[i]);
+    i += 1;
+  }
+  String::from_utf8_lossy(&out).to_string()
+}
+
+pub enum Resolved {
+  File(PathBuf),
+  Redirect(String),
+  Missing
+}
+
+// work out which file a URL path refers to
+// /about/ -> about/index.html, /about -> about.html if there is no about/ directory
+pub fn resolve(root: &Path, url_path: &str) -> Resolved {
+  let decoded = decode(url_path);
+  let relative = Path::new(decoded.trim_start_matches('/'));
+  // don't serve anything outside the output directory
+  if relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+    return Resolved::Missing
+  }
+  let path = root.join(relative);
+  if path.is_dir() {
+    if !url_path.ends_with('/') {
+      // so that relative links inside the page work
+      return Resolved::Redirect([url_path, "/"].concat())
+    }
+    let index = path.join("index.html");
+    return if index.is_file() { Resolved::File(index) } else { Resolved::Missing }
+  }
+  if path.is_file() {
+    return Resolved::File(path)
+  }
+  let html = path.with_extension("html");
+  if path.extension().is_none() && html.is_file() {
+    return Resolved::File(html)
+  }
+  Resolved::Missing
+}
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn file_response(path: &Path, status: u16, live_reload: bool) -> io::Result<Response<io::Cursor<Vec<u8>>>> {
+  let mut body = fs::read(path)?;
+  let mime = content_type(path);
+  if live_reload && mime.starts_with("text/html") {
+    let html = String::from_utf8_lossy(&body).to_string();
+    body = match html.rfind("</body>") {
+      Some(i) => [&html[..i], RELOAD_SCRIPT, &html[i..]].concat(),
+      None => [html.as_str(), RELOAD_SCRIPT].concat()
+    }.into_bytes();
+  }
+  Ok(Response::from_data(body)
+    .with_status_code(StatusCode(status))
+    .with_header(header("Content-Type", mime))
+    .with_header(header("Cache-Control", "no-cache")))
+}
+
+// the live reload script asks for this, and reloads when it changes
+fn output_version(root: &Path) -> String {
+  let mut files = HashMap::new();
+  snapshot(root, &mut files);
+  let latest = files.values()
+    .filter_map(|t| t.duration_since(UNIX_EPOCH).ok())
+    .max()
+    .map(|d| d.as_millis())
+    .unwrap_or(0);
+  [latest.to_string(), "-".to_string(), files.len().to_string()].concat()
+}
+
+fn handle(request: Request, root: &Path, live_reload: bool) -> io::Result<()> {
+  if request.method() != &Method::Get && request.method() != &Method::Head {
+    return request.respond(Response::from_string("Method not allowed").with_status_code(StatusCode(405)))
+  }
+  let url = request.url().to_string();
+  let url_path = url.split(['?', '#']).next().unwrap_or("/");
+
+  if live_reload && url_path == RELOAD_PATH {
+    let version = output_version(root);
+    return request.respond(Response::from_string(version).with_header(header("Cache-Control", "no-cache")))
+  }
+
+  match resolve(root, url_path) {
+    Resolved::File(path) => request.respond(file_response(&path, 200, live_reload)?),
+    Resolved::Redirect(location) => request.respond(
+      Response::empty(StatusCode(301)).with_header(header("Location", &location))
+    ),
+    Resolved::Missing => {
+      // use the site's own 404 page if it has one
+      let custom = root.join("404.html");
+      if custom.is_file() {
+        request.respond(file_response(&custom, 404, live_reload)?)
+      } else {
+        request.respond(Response::from_string("Not found").with_status_code(StatusCode(404)))
+      }
+    }
+  }
+}
+
+pub struct Server {
+  server: Arc<tiny_http::Server>,
+  thread: JoinHandle<()>,
+  pub url: String
+}
+
+impl Server {
+  pub fn stop(self) {
+    self.server.unblock();
+    let _ = self.thread.join();
+  }
+}
+
+pub fn start(config: &Config) -> io::Result<Server> {
+  let root = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let server = tiny_http::Server::http(&config.serve_address)
+    .map_err(|e| io::Error::new(io::ErrorKind::AddrInUse, format!("Couldn't start a server at {}: {}", config.serve_address, e)))?;
+  let server = Arc::new(server);
+  let url = ["http://", &config.serve_address].concat();
+  let live_reload = config.live_reload;
+
+  let incoming = Arc::clone(&server);
+  let thread = thread::spawn(move || {
+    for request in incoming.incoming_requests() {
+      if let Err(e) = handle(request, &root, live_reload) {
+        eprintln!("😬 error serving request: {}", e);
+      }
+    }
+  });
+  Ok(Server { server, thread, url })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a throwaway output directory
+  fn site(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("letters-serve-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("about")).unwrap();
+    fs::create_dir_all(root.join("empty")).unwrap();
+    fs::create_dir_all(root.join("a b")).unwrap();
+    fs::write(root.join("index.html"), "home").unwrap();
+    fs::write(root.join("about/index.html"), "about").unwrap();
+    fs::write(root.join("contact.html"), "contact").unwrap();
+    fs::write(root.join("a b/c.txt"), "c").unwrap();
+    root
+  }
+
+  fn file(resolved: Resolved) -> Option<PathBuf> {
+    match resolved {
+      Resolved::File(f) => Some(f),
+      _ => None
+    }
+  }
+
+  #[test]
+  fn decodes_percent_escapes() {
+    assert_eq!(decode("a%20b"), "a b");
+    assert_eq!(decode("caf%C3%A9"), "café");
+    assert_eq!(decode("100%"), "100%");
+    assert_eq!(decode("%zz%2"), "%zz%2");
+  }
+
+  #[test]
+  fn resolves_clean_urls() {
+    let root = site("clean");
+    assert_eq!(file(resolve(&root, "/")), Some(root.join("index.html")));
+    assert_eq!(file(resolve(&root, "/about/")), Some(root.join("about/index.html")));
+    assert_eq!(file(resolve(&root, "/contact")), Some(root.join("contact.html")));
+    assert_eq!(file(resolve(&root, "/a%20b/c.txt")), Some(root.join("a b/c.txt")));
+    assert!(matches!(resolve(&root, "/about"), Resolved::Redirect(l) if l == "/about/"));
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn missing_files_and_escapes_are_not_found() {
+    let root = site("missing");
+    assert!(matches!(resolve(&root, "/nope"), Resolved::Missing));
+    assert!(matches!(resolve(&root, "/empty/"), Resolved::Missing));
+    assert!(matches!(resolve(&root, "/../etc/passwd"), Resolved::Missing));
+    assert!(matches!(resolve(&root, "/%2e%2e/etc/passwd"), Resolved::Missing));
+    fs::remove_dir_all(root).unwrap();
+  }
+}
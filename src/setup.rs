@@ -0,0 +1,528 @@
+use colol::{color, close_color};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use subprocess::{Exec, NullFile};
+use toml::Value;
+use toml::value::Table;
+
+// Every value we know about, with the comment that goes next to it in the config file
+// (key, example value shown when commented out, comment, required)
+
+const TOP_LEVEL: [(&str, &str, &str, bool); 26] = [
+  ("author", "\"\"", "your name", true),
+  ("input", "\"\"", "the input directory for your site i.e. where your markdown files go", true),
+  ("output", "\"\"", "the directory for processed files for your site i.e. where your html files go. Do NOT include a trailing slash as lette.rs will add this for you", true),
+  ("workdir", "\"\"", "the base directory for calling your static site commands. Probably the root directory for eleventy, Hugo etc", true),
+  ("remote_dir", "\"\"", "the directory to rsync files to, on your remote server. Only needed to publish with rsync or sftp", false),
+  ("rss_file", "\"\"", "filepath to the RSS file in your output directory", true),
+  ("server_name", "\"\"", "this could be a name if you have set one in ~/.ssh/config, or otherwise an IP address. Only needed to publish with rsync or sftp", false),
+  ("unsplash_client_id", "\"\"", "unsplash client ID string", false),
+  ("test_url", "\"\"", "if your SSG serves your site locally this should be the localhost URL where you can see it. eleventy and hugo will use their respective defaults if you don't provide a value. ", false),
+  ("ssg_type", "\"\"", "your static site generator. Options that will do something are \"hugo\", \"eleventy\" or \"builtin\" but you can try something else and see if it works. Defaults to \"eleventy\"", false),
+  ("default_layout", "\"\"", "use any string, this will be the value of \"layout\" in your frontmatter. Defaults to \"post\"", false),
+  ("site_title", "\"\"", "the name of your site, used by the builtin generator. Defaults to your name", false),
+  ("site_url", "\"\"", "the public URL of your site e.g. https://example.com", false),
+  ("template_dir", "\"\"", "HTML templates for the builtin generator, relative to workdir. Defaults to \"templates\"", false),
+  ("extra_paths", "[]", "other files or directories your site is built from, relative to workdir e.g. [\"_includes\", \".eleventy.js\"]", false),
+  ("test_timeout", "30", "how many seconds to wait for your test server to start. Defaults to 30", false),
+  ("browser", "\"\"", "command to open a browser when testing e.g. \"firefox\". Defaults to $BROWSER or your system default", false),
+  ("serve_address", "\"\"", "address for the builtin test server, used if there is no 'test' command and your SSG can't serve your site. Defaults to \"localhost:8080\"", false),
+  ("live_reload", "false", "reload the page in your browser when your site changes, when using the builtin test server", false),
+  ("check_before_publish", "false", "check for broken links with 'letters check' every time you publish, and don't publish if there are any", false),
+  ("check_external", "false", "also check links to other sites when checking links. Links that worked are remembered for a week", false),
+  ("confirm_deletions", "20", "ask before publishing if it would delete more than this many files from your server. Defaults to 20", false),
+  ("publish_method", "\"\"", "how to publish your site: \"rsync\" runs your 'publish' command, \"sftp\" uploads changed files with sftp, \"s3\" uploads them to the bucket in [s3], \"git\" pushes them to the branch in [git]. Defaults to \"rsync\"", false),
+  ("atomic_releases", "false", "publish each time into a new directory under remote_dir/releases and switch the remote_dir/current symlink to it, so you can 'letters rollback'. Needs rsync, and your web server pointed at remote_dir/current", false),
+  ("keep_releases", "5", "how many releases to keep on the server when atomic_releases is on. Defaults to 5", false),
+  ("default_target", "\"\"", "the name of one of your [[targets]] to publish to, unless you choose another with --target. If not set, lette.rs publishes to server_name and remote_dir", false)
+];
+
+const COMMANDS: [(&str, &str); 4] = [
+  ("process", "command to process files"),
+  ("publish", "defaults to 'rsync -rtO --del --quiet'"),
+  ("test", "command to serve site locally (if your SSG enables that)"),
+  ("watch_hook", "command to run after each rebuild with 'letters watch'")
+];
+
+const SOCIAL: [(&str, &str); 6] = [
+  ("mastodon_access_token", ""),
+  ("mastodon_base_url", "e.g. https://example.com"),
+  ("twitter_consumer_key", ""),
+  ("twitter_consumer_secret", ""),
+  ("twitter_access_token", ""),
+  ("twitter_access_secret", "")
+];
+
+fn config_path() -> Option<String> {
+  match shellexpand::full("~/.letters.toml") {
+    Ok(p) => Some(p.to_string()),
+    Err(e) => {
+      eprintln!("There was an error reading the default config path:, {}", e);
+      None
+    }
+  }
+}
+
+// Write out a config file from a table of values
+// Known values get their explanatory comment, blank optional values are commented out
+// and anything else in the table (e.g. added by hand) is kept as-is
+fn render(conf: &Table) -> String {
+
+  fn line(key: &str, value: Option<&Value>, placeholder: &str, comment: &str, required: bool) -> String {
+    let v = match value {
+      Some(Value::String(s)) if s.is_empty() => None,
+      Some(v) => Some(v.to_string()),
+      None => None
+    };
+    let mut l = String::new();
+    if v.is_none() && !required {
+      l.push_str("# ");
+    }
+    l.push_str(key);
+    l.push_str(" = ");
+    l.push_str(&v.unwrap_or_else(|| placeholder.to_string()));
+    if !comment.is_empty() {
+      l.push_str(" # ");
+      l.push_str(comment);
+    }
+    l
+  }
+
+  // values we don't ask about in the wizard but which should survive a rewrite
+  fn extras(table: &Table, known: &[&str]) -> Vec<String> {
+    table.iter()
+      .filter(|(k, v)| !known.contains(&k.as_str()) && !is_table(v))
+      .map(|(k, v)| format!("{} = {}", k, v))
+      .collect()
+  }
+
+  fn is_table(v: &Value) -> bool {
+    match v {
+      Value::Table(_) => true,
+      Value::Array(a) => !a.is_empty() && a.iter().all(|i| i.is_table()),
+      _ => false
+    }
+  }
+
+  let empty = Table::new();
+  let commands = conf.get("commands").and_then(|c| c.as_table()).unwrap_or(&empty);
+  let social = conf.get("social").and_then(|c| c.as_table()).unwrap_or(&empty);
+
+  let mut file = Vec::new();
+  for (key, placeholder, comment, required) in TOP_LEVEL.iter().filter(|t| t.3) {
+    file.push(line(key, conf.get(*key), placeholder, comment, *required));
+  }
+  file.push(String::from("\n# All the values below are optional. Remove the '#' to uncomment them if you wish to override the default or set a value\n"));
+  for (key, placeholder, comment, required) in TOP_LEVEL.iter().filter(|t| !t.3) {
+    file.push(line(key, conf.get(*key), placeholder, comment, *required));
+  }
+  let known: Vec<&str> = TOP_LEVEL.iter().map(|t| t.0).collect();
+  file.append(&mut extras(conf, &known));
+
+  file.push(String::from("\n"));
+  file.push(String::from("[commands]"));
+  file.push(String::from("# You can override the defaults by setting one of the values below, but if using Hugo or Eleventy you don't need to do so."));
+  for (key, comment) in COMMANDS.iter() {
+    file.push(line(key, commands.get(*key), "\"\"", comment, false));
+  }
+  let known: Vec<&str> = COMMANDS.iter().map(|t| t.0).collect();
+  file.append(&mut extras(commands, &known));
+
+  file.push(String::from("\n"));
+  file.push(String::from("[social]"));
+  file.push(String::from("# uncomment and set values below as needed"));
+  for (key, comment) in SOCIAL.iter() {
+    file.push(line(key, social.get(*key), "\"\"", comment, false));
+  }
+  let known: Vec<&str> = SOCIAL.iter().map(|t| t.0).collect();
+  file.append(&mut extras(social, &known));
+
+  // any other tables go at the end
+  let mut others = Table::new();
+  for (k, v) in conf.iter() {
+    if k != "commands" && k != "social" && is_table(v) {
+      others.insert(k.to_string(), v.clone());
+    }
+  }
+  if !others.is_empty() {
+    file.push(String::from("\n"));
+    file.push(toml::to_string(&others).unwrap_or_default());
+  }
+
+  let mut conf = file.join("\n");
+  conf.push('\n');
+  conf
+}
+
+// setup --edit
+// Open the config file with the system default app, creating a template first if there isn't one
+
+fn open_file(cmd: &str) {
+  Exec::shell(cmd).join().unwrap();
+}
+
+fn prep_to_open_file() {
+  let os = env::consts::OS;
+  match os {
+    "macos" => open_file("open ~/.letters.toml"),
+    "linux" | "freebsd" | "openbsd" => open_file("xdg-open ~/.letters.toml"),
+    &_ => ()
+  }
+}
+
+pub fn edit() {
+
+  fn create_file(path: &str) {
+    match fs::write(path, render(&Table::new())) {
+      Ok(_) => prep_to_open_file(),
+      Err(e) => println!("Error opening file {}: {}", path, e)
+    };
+  }
+
+  fn choose_file(f: &str) {
+    let file = fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(f);
+
+    match file {
+    // no error means the file did not exist
+    Ok(_) => create_file(f),
+    // error here means the file exists
+    Err(_error) => prep_to_open_file()
+    };
+  }
+
+  if let Some(p) = config_path() {
+    choose_file(&p)
+  }
+}
+
+// setup
+// Ask for each value in the terminal, check it makes sense, then write the config file
+// This works over SSH, unlike opening the file in an app
+
+fn ask(question: &str, default: &str, secret: bool) -> String {
+  color!(bold);
+  color!(green);
+  if default.is_empty() {
+    print!("{}: ", question);
+  } else if secret {
+    print!("{} [********]: ", question);
+  } else {
+    print!("{} [{}]: ", question, default);
+  }
+  color!(gray);
+  close_color!(bold);
+  io::stdout().flush().unwrap();
+  let mut answer = String::new();
+  let read = io::stdin().read_line(&mut answer);
+  color!(reset);
+  // there's nobody to ask, e.g. in cron or CI, so asking again would go on forever
+  if !matches!(read, Ok(n) if n > 0) {
+    println!();
+    eprintln!("😭 There's no terminal to answer questions in. Run 'letters setup' in a terminal, or 'letters setup --edit' to write the config file yourself.");
+    process::exit(1);
+  }
+  // Enter keeps the default, '-' clears it
+  match answer.trim() {
+    "" => default.to_string(),
+    "-" => String::new(),
+    a => a.to_string()
+  }
+}
+
+pub fn ask_yes_no(question: &str, default: bool) -> bool {
+  let options = if default { "Y/n" } else { "y/N" };
+  let answer = ask(&format!("{} ({})", question, options), "", false);
+  match answer.to_lowercase().as_str() {
+    "y" | "yes" => true,
+    "n" | "no" => false,
+    _ => default
+  }
+}
+
+fn ask_required(question: &str, default: &str) -> String {
+  loop {
+    let answer = ask(question, default, false);
+    if answer.is_empty() {
+      println!("😬 this value is required");
+    } else {
+      return answer
+    }
+  }
+}
+
+// local directories have to exist, but we can create them if the user wants
+fn ask_directory(question: &str, default: &str) -> String {
+  loop {
+    let answer = ask_required(question, default);
+    let expanded = match shellexpand::full(&answer) {
+      Ok(p) => p.to_string(),
+      Err(e) => {
+        println!("😬 can't read that path: {}", e);
+        continue
+      }
+    };
+    if Path::new(&expanded).is_dir() {
+      return answer
+    }
+    if ask_yes_no(&format!("{} does not exist. Create it?", expanded), false) {
+      match fs::create_dir_all(&expanded) {
+        Ok(_) => return answer,
+        Err(e) => println!("😬 couldn't create {}: {}", expanded, e)
+      }
+    }
+  }
+}
+
+fn ask_url(question: &str, default: &str) -> String {
+  loop {
+    let answer = ask(question, default, false);
+    if answer.is_empty() || answer.starts_with("http://") || answer.starts_with("https://") {
+      return answer.trim_end_matches('/').to_string()
+    }
+    println!("😬 that doesn't look like a URL, it should start with http:// or https://");
+  }
+}
+
+// run a command on the server the same way rsync does, without allowing password prompts
+fn ssh(server: &str, command: &str) -> bool {
+  Exec::cmd("ssh")
+    .args(&["-o", "BatchMode=yes", "-o", "ConnectTimeout=10", server, command])
+    .stdout(NullFile)
+    .stderr(NullFile)
+    .join()
+    .map(|s| s.success())
+    .unwrap_or(false)
+}
+
+fn ask_server(default: &str) -> String {
+  loop {
+    let answer = ask_required("Server name or IP address (as you would use with ssh)", default);
+    println!("Checking we can log in to {}...", answer);
+    if ssh(&answer, "exit") {
+      println!("✅ connected to {}", answer);
+      return answer
+    }
+    println!("😬 couldn't log in to {} with ssh. Check it's in ~/.ssh/config or that your key is loaded in your ssh agent.", answer);
+    if ask_yes_no("Use this value anyway?", false) {
+      return answer
+    }
+  }
+}
+
+fn ask_remote_dir(server: &str, default: &str) -> String {
+  loop {
+    let answer = ask_required("Remote directory (where your site lives on the server)", default);
+    if ssh(server, &["test -d ", &shell_words::quote(&answer)].concat()) {
+      return answer
+    }
+    println!("😬 couldn't find {} on {}", answer, server);
+    if ask_yes_no("Use this value anyway?", false) {
+      return answer
+    }
+  }
+}
+
+// returns the account name if the token works
+fn verify_mastodon(base_url: &str, token: &str) -> Result<String, String> {
+  let endpoint = [base_url, "/api/v1/accounts/verify_credentials"].concat();
+  let auth = ["Bearer ", token].concat();
+  let resp = ureq::get(&endpoint)
+    .set("Authorization", &auth)
+    .call();
+  if let Some(err) = resp.synthetic_error() {
+    return Err(err.to_string())
+  }
+  if !resp.ok() {
+    return Err(format!("{} returned error code {}", base_url, resp.status()))
+  }
+  let json = resp.into_json().map_err(|e| e.to_string())?;
+  Ok(json["acct"].as_str().unwrap_or("").to_string())
+}
+
+fn ask_mastodon(social: &mut Table) {
+  loop {
+    let base_url = ask_url("Mastodon base URL (e.g. https://example.com)", &get(social, "mastodon_base_url"));
+    let token = ask("Mastodon access token", &get(social, "mastodon_access_token"), true);
+    set(social, "mastodon_base_url", &base_url);
+    set(social, "mastodon_access_token", &token);
+    if base_url.is_empty() || token.is_empty() {
+      println!("😬 you need both a base URL and an access token to toot");
+    } else {
+      println!("Checking your Mastodon token...");
+      match verify_mastodon(&base_url, &token) {
+        Ok(acct) => {
+          println!("✅ authenticated as @{}", acct);
+          return
+        },
+        Err(e) => println!("😬 couldn't verify your Mastodon credentials: {}", e)
+      }
+    }
+    if ask_yes_no("Use these values anyway?", false) {
+      return
+    }
+  }
+}
+
+fn get(table: &Table, key: &str) -> String {
+  table.get(key).and_then(|v| v.as_str()).unwrap_or("").to_string()
+}
+
+fn set(table: &mut Table, key: &str, value: &str) {
+  table.insert(key.to_string(), Value::String(value.to_string()));
+}
+
+fn subtable(table: &mut Table, key: &str) -> Table {
+  match table.remove(key) {
+    Some(Value::Table(t)) => t,
+    _ => Table::new()
+  }
+}
+
+pub fn wizard() {
+
+  colol::init();
+  let path = match config_path() {
+    Some(p) => p,
+    None => return
+  };
+
+  // start from the existing file if there is one, so Enter keeps the current values
+  let mut conf = match fs::read_to_string(&path) {
+    Ok(s) => match s.parse::<Value>() {
+      Ok(Value::Table(t)) => t,
+      _ => {
+        println!("😬 your existing config file can't be read, so we'll start from scratch.");
+        Table::new()
+      }
+    },
+    Err(_) => Table::new()
+  };
+
+  println!("Let's set up lette.rs! Press Enter to keep the value in [brackets], or type '-' to clear it.\n");
+
+  let author = ask_required("Your name", &get(&conf, "author"));
+  set(&mut conf, "author", &author);
+  let input = ask_directory("Input directory (where your markdown files go)", &get(&conf, "input"));
+  set(&mut conf, "input", &input);
+  let output = ask_directory("Output directory (where your html files go)", &get(&conf, "output"));
+  set(&mut conf, "output", output.trim_end_matches('/'));
+  let workdir = ask_directory("Working directory (where you run your static site commands)", &get(&conf, "workdir"));
+  set(&mut conf, "workdir", &workdir);
+
+  let rss_file = ask_required("RSS file (full filepath in your output directory)", &get(&conf, "rss_file"));
+  if let Ok(p) = shellexpand::full(&rss_file) {
+    if !Path::new(p.as_ref()).is_file() {
+      println!("👀 {} doesn't exist yet. That's fine if you haven't run 'letters process' yet.", p);
+    }
+  }
+  set(&mut conf, "rss_file", &rss_file);
+
+  // only rsync and sftp need a server, s3 and git need their own table instead
+  let current_method = get(&conf, "publish_method");
+  let publish_method = loop {
+    let answer = ask("How do you publish your site? (rsync, sftp, s3 or git)", if current_method.is_empty() { "rsync" } else { &current_method }, false);
+    if ["rsync", "sftp", "s3", "git"].contains(&answer.as_str()) {
+      break answer
+    }
+    println!("😬 lette.rs can publish with rsync, sftp, s3 or git");
+  };
+  set(&mut conf, "publish_method", &publish_method);
+  match publish_method.as_str() {
+    "s3" => {
+      let mut s3 = subtable(&mut conf, "s3");
+      let bucket = ask_required("Bucket name", &get(&s3, "bucket"));
+      set(&mut s3, "bucket", &bucket);
+      let region = ask("Region (leave blank for us-east-1)", &get(&s3, "region"), false);
+      let endpoint = ask_url("Endpoint, if it isn't Amazon S3 (e.g. https://s3.example.com)", &get(&s3, "endpoint"));
+      // tables are written out as they are, so leave blank values out rather than writing empty strings
+      for (key, value) in [("region", &region), ("endpoint", &endpoint)] {
+        if value.is_empty() {
+          s3.remove(key);
+        } else {
+          set(&mut s3, key, value);
+        }
+      }
+      println!("👀 set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, or access_key and secret_key in [s3], so lette.rs can upload to {}", bucket);
+      conf.insert(String::from("s3"), Value::Table(s3));
+    },
+    "git" => {
+      let mut git = subtable(&mut conf, "git");
+      let repo = ask_required("Repository to push to (a URL or a path)", &get(&git, "repo"));
+      set(&mut git, "repo", &repo);
+      let current_branch = get(&git, "branch");
+      let branch = ask_required("Branch to publish to", if current_branch.is_empty() { "gh-pages" } else { &current_branch });
+      set(&mut git, "branch", &branch);
+      conf.insert(String::from("git"), Value::Table(git));
+    },
+    _ => {
+      let server_name = ask_server(&get(&conf, "server_name"));
+      set(&mut conf, "server_name", &server_name);
+      let remote_dir = ask_remote_dir(&server_name, &get(&conf, "remote_dir"));
+      set(&mut conf, "remote_dir", &remote_dir);
+    }
+  }
+
+  let current_ssg = get(&conf, "ssg_type");
+  let ssg_type = ask("Static site generator (eleventy, hugo or builtin)", if current_ssg.is_empty() { "eleventy" } else { &current_ssg }, false);
+  if !["", "eleventy", "hugo", "builtin"].contains(&ssg_type.as_str()) {
+    println!("👀 lette.rs doesn't know about {}, so you'll probably need to set your own commands.", ssg_type);
+  }
+  set(&mut conf, "ssg_type", &ssg_type);
+
+  let site_url = ask_url("Public URL of your site (e.g. https://example.com)", &get(&conf, "site_url"));
+  set(&mut conf, "site_url", &site_url);
+  if ssg_type == "builtin" {
+    let site_title = ask("Site title", &get(&conf, "site_title"), false);
+    set(&mut conf, "site_title", &site_title);
+    let template_dir = ask("Template directory, relative to your working directory", &get(&conf, "template_dir"), false);
+    set(&mut conf, "template_dir", &template_dir);
+  }
+
+  let test_url = ask_url("Local test URL (leave blank for the default)", &get(&conf, "test_url"));
+  set(&mut conf, "test_url", &test_url);
+  let current_layout = get(&conf, "default_layout");
+  let default_layout = ask("Default layout", if current_layout.is_empty() { "post" } else { &current_layout }, false);
+  set(&mut conf, "default_layout", &default_layout);
+  let unsplash = ask("Unsplash client ID (leave blank if you use --no-image)", &get(&conf, "unsplash_client_id"), true);
+  set(&mut conf, "unsplash_client_id", &unsplash);
+
+  let mut commands = subtable(&mut conf, "commands");
+  let has_commands = COMMANDS.iter().any(|c| !get(&commands, c.0).is_empty());
+  if ask_yes_no("Do you want to override the default commands?", has_commands) {
+    for (key, comment) in COMMANDS.iter() {
+      let value = ask(&format!("'{}' command ({})", key, comment), &get(&commands, key), false);
+      set(&mut commands, key, &value);
+    }
+  }
+  conf.insert(String::from("commands"), Value::Table(commands));
+
+  let mut social = subtable(&mut conf, "social");
+  let has_mastodon = !get(&social, "mastodon_access_token").is_empty();
+  if ask_yes_no("Do you want to toot to Mastodon when you publish?", has_mastodon) {
+    ask_mastodon(&mut social);
+  }
+  let has_twitter = !get(&social, "twitter_access_token").is_empty();
+  if ask_yes_no("Do you want to tweet when you publish?", has_twitter) {
+    for (key, _comment) in SOCIAL.iter().filter(|s| s.0.starts_with("twitter")) {
+      let value = ask(&key.replace('_', " "), &get(&social, key), true);
+      set(&mut social, key, &value);
+    }
+  }
+  conf.insert(String::from("social"), Value::Table(social));
+
+  println!();
+  if ask_yes_no(&format!("Save config to {}?", path), true) {
+    match fs::write(&path, render(&conf)) {
+      Ok(_) => println!("✅ saved! Run 'letters setup --edit' any time to edit the file directly."),
+      Err(e) => eprintln!("Error writing file {}: {}", path, e)
+    }
+  } else {
+    println!("Nothing saved.");
+  }
+}
@@ -0,0 +1,260 @@
+use crate::changes::{self, Changes};
+use crate::Config;
+use chrono::{DateTime, Local};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+use subprocess::{Exec, ExitStatus, Redirection};
+
+// Publishes with sftp instead of rsync, for servers without rsync or with sftp-only accounts
+// sftp uses your ssh config and agent, so server_name works the same way it does for rsync
+// sftp can't hash files on the server, so like rsync we compare the size and modification time
+// of each file there with the one in output, and only upload files that are different
+// Files are uploaded with their modification times, so they match next time
+// sftp only shows times to the minute, so a change that keeps the same size within a minute of the last upload is missed
+
+// batch files are numbered, so running more than one publish at once is fine
+static BATCHES: AtomicUsize = AtomicUsize::new(0);
+
+// a file on the server
+struct RemoteFile {
+  size: u64,
+  // the modification time as sftp shows it, e.g. "Oct 9 06:10", or "Oct 9 2020" for older files
+  modified: String
+}
+
+// a file in output
+struct LocalFile {
+  size: u64,
+  modified: SystemTime
+}
+
+// sftp batch files use double quotes with backslash escapes
+fn quote(s: &str) -> String {
+  ["\"", &s.replace('\\', "\\\\").replace('"', "\\\""), "\""].concat()
+}
+
+fn remote_path(remote: &str, path: &str) -> String {
+  [remote.trim_end_matches('/'), "/", path].concat()
+}
+
+// every directory above a path, parents first, e.g. posts, posts/one
+fn parents(path: &str) -> Vec<String> {
+  let parts: Vec<&str> = path.split('/').collect();
+  (1..parts.len()).map(|i| parts[..i].join("/")).collect()
+}
+
+// runs a list of sftp commands, returning whether it worked and what sftp said
+fn run(config: &Config, script: &[String]) -> io::Result<(bool, String)> {
+  let number = BATCHES.fetch_add(1, Ordering::SeqCst);
+  let batch = env::temp_dir().join(format!("letters-sftp-{}-{}", process::id(), number));
+  fs::OpenOptions::new().write(true).create_new(true).open(&batch)?;
+  fs::write(&batch, script.join("\n"))?;
+  let capture = Exec::cmd("sftp")
+    .arg("-q")
+    .arg("-b")
+    .arg(&batch)
+    .arg(&config.server_name)
+    // [env] applies here too, e.g. SSH_AUTH_SOCK
+    .env_extend(&config.env.iter().collect::<Vec<_>>())
+    .stdout(Redirection::Pipe)
+    .stderr(Redirection::Merge)
+    .capture()
+    .map_err(|e| io::Error::other(e.to_string()));
+  let _ = fs::remove_file(&batch);
+  let capture = capture?;
+  Ok((capture.exit_status == ExitStatus::Exited(0), capture.stdout_str()))
+}
+
+// one line from 'ls -lan', e.g. "-rw-r--r--    1 1000     1000         1234 Oct  9 06:10 /var/www/index.html"
+// returns the name, whether it's a directory, and the file
+fn parse_line(line: &str) -> Option<(String, bool, RemoteFile)> {
+  let mut fields = Vec::new();
+  let mut rest = line;
+  for _ in 0..8 {
+    rest = rest.trim_start();
+    let end = rest.find(char::is_whitespace)?;
+    fields.push(&rest[..end]);
+    rest = &rest[end..];
+  }
+  let permissions = fields[0];
+  if permissions.len() < 10 || !"-dlcbps".contains(&permissions[..1]) {
+    return None
+  }
+  let size = fields[4].parse().ok()?;
+  let name = rest.strip_prefix(' ')?.to_string();
+  Some((name, permissions.starts_with('d'), RemoteFile { size, modified: fields[5..].join(" ") }))
+}
+
+// sftp shows the local time, and the year instead of the time for files more than six months old
+fn same_time(shown: &str, modified: SystemTime) -> bool {
+  let local: DateTime<Local> = modified.into();
+  let format = if shown.contains(':') { "%b %-d %H:%M" } else { "%b %-d %Y" };
+  local.format(format).to_string() == shown
+}
+
+// every file under remote_dir on the server, by path relative to it
+// each level of directories is listed in one go, so this connects once per level
+fn remote_files(config: &Config, remote: &str) -> io::Result<BTreeMap<String, RemoteFile>> {
+  let prefix = remote_path(remote, "");
+  let mut files = BTreeMap::new();
+  let mut dirs = vec![String::new()];
+  while !dirs.is_empty() {
+    // a leading - means sftp carries on if remote_dir isn't there yet
+    let script: Vec<String> = dirs.iter()
+      .map(|d| format!("-ls -lan {}", quote(&remote_path(remote, d))))
+      .collect();
+    let (worked, output) = run(config, &script)?;
+    if !worked {
+      return Err(io::Error::other(format!("couldn't connect to {} with sftp:\n{}", config.server_name, output.trim_end())))
+    }
+    dirs.clear();
+    for (name, is_dir, file) in output.lines().filter(|l| !l.starts_with("sftp>")).filter_map(parse_line) {
+      let relative = match name.strip_prefix(&prefix) {
+        Some(r) if !r.is_empty() => r.to_string(),
+        _ => continue
+      };
+      if relative.rsplit('/').next().map(|n| n == "." || n == "..").unwrap_or(true) {
+        continue
+      }
+      if is_dir {
+        dirs.push(relative);
+      } else {
+        files.insert(relative, file);
+      }
+    }
+  }
+  Ok(files)
+}
+
+// every file in the output directory, with paths relative to it so they match the server
+fn local_files(dir: &Path, output: &Path, files: &mut BTreeMap<String, LocalFile>) -> io::Result<()> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      local_files(&path, output, files)?;
+    } else if let Ok(relative) = path.strip_prefix(output) {
+      let metadata = fs::metadata(&path)?;
+      let relative = relative.to_string_lossy().replace('\\', "/");
+      files.insert(relative, LocalFile { size: metadata.len(), modified: metadata.modified()? });
+    }
+  }
+  Ok(())
+}
+
+fn compare(server: &BTreeMap<String, RemoteFile>, output: &BTreeMap<String, LocalFile>) -> Changes {
+  let mut changes = Changes::default();
+  for (path, local) in output {
+    match server.get(path) {
+      None => changes.added.push(path.clone()),
+      Some(remote) if remote.size != local.size || !same_time(&remote.modified, local.modified) => changes.updated.push(path.clone()),
+      _ => ()
+    }
+  }
+  changes.deleted = server.keys().filter(|p| !output.contains_key(*p)).cloned().collect();
+  changes
+}
+
+fn locations(config: &Config) -> (PathBuf, String) {
+  let output = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let remote = shellexpand::full(&config.remote_dir).expect("Error reading remote directory").to_string();
+  (output, remote)
+}
+
+// what publishing would change, and every file in output
+pub fn plan(config: &Config) -> io::Result<(Changes, BTreeSet<String>)> {
+  let (output, remote) = locations(config);
+  let server = remote_files(config, &remote)?;
+  let mut local = BTreeMap::new();
+  local_files(&output, &output, &mut local)?;
+  Ok((compare(&server, &local), local.into_keys().collect()))
+}
+
+pub fn publish(config: &Config) -> io::Result<(bool, Changes)> {
+  let (output, remote) = locations(config);
+  let (changes, local) = plan(config)?;
+  if changes.is_empty() {
+    println!("👌 Nothing to publish, the server is already up to date");
+    return Ok((true, changes))
+  }
+  let destination = [&config.server_name, ":", &remote].concat();
+  if !changes::confirm(config, &changes, &destination) {
+    println!("Not publishing");
+    return Ok((false, changes))
+  }
+
+  let uploads: Vec<&String> = changes.added.iter().chain(changes.updated.iter()).collect();
+  let mut script = vec![format!("-mkdir {}", quote(&remote))];
+  let dirs: BTreeSet<String> = uploads.iter().flat_map(|p| parents(p)).collect();
+  for dir in &dirs {
+    let path = quote(&remote_path(&remote, dir));
+    // these fail if the directory is already there, or isn't ours
+    script.push(format!("-mkdir {}", path));
+    script.push(format!("-chmod {} {}", config.sftp.dir_mode, path));
+  }
+  for path in &uploads {
+    let local = output.join(path);
+    let target = quote(&remote_path(&remote, path));
+    // -p keeps the modification time, which is how we know it's up to date next time
+    script.push(format!("put -p {} {}", quote(&local.to_string_lossy()), target));
+    script.push(format!("chmod {} {}", config.sftp.file_mode, target));
+  }
+  for path in &changes.deleted {
+    script.push(format!("-rm {}", quote(&remote_path(&remote, path))));
+  }
+  // remove directories that are now empty, deepest first
+  let kept: BTreeSet<String> = local.iter().flat_map(|p| parents(p)).collect();
+  let empty: BTreeSet<String> = changes.deleted.iter().flat_map(|p| parents(p)).filter(|d| !kept.contains(d)).collect();
+  for dir in empty.iter().rev() {
+    script.push(format!("-rmdir {}", quote(&remote_path(&remote, dir))));
+  }
+
+  println!("Uploading {} files and deleting {} with sftp...", uploads.len(), changes.deleted.len());
+  let (worked, said) = run(config, &script)?;
+  if !worked {
+    eprintln!("{}", said.trim_end());
+  }
+  Ok((worked, changes))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+
+  #[test]
+  fn parses_long_listings() {
+    let (name, is_dir, file) = parse_line("-rw-r--r--    1 1000     1000         1234 Oct  9 06:10 /var/www/a b.html").unwrap();
+    assert_eq!(name, "/var/www/a b.html");
+    assert!(!is_dir);
+    assert_eq!(file.size, 1234);
+    assert_eq!(file.modified, "Oct 9 06:10");
+    let (name, is_dir, file) = parse_line("drwxr-xr-x    2 0        0            4096 Mar 14  2020 posts").unwrap();
+    assert_eq!(name, "posts");
+    assert!(is_dir);
+    assert_eq!(file.modified, "Mar 14 2020");
+  }
+
+  #[test]
+  fn ignores_everything_else() {
+    assert!(parse_line("sftp> -ls -lan \"/var/www/a b c d e f g\"").is_none());
+    assert!(parse_line("Can't ls: \"/var/www\" not found").is_none());
+    assert!(parse_line("").is_none());
+  }
+
+  #[test]
+  fn compares_times_the_way_sftp_shows_them() {
+    let recent = Local::now() - chrono::Duration::days(3);
+    let shown = recent.format("%b %-d %H:%M").to_string();
+    assert!(same_time(&shown, recent.into()));
+    assert!(!same_time(&shown, (recent + chrono::Duration::minutes(1)).into()));
+    let old = Local.with_ymd_and_hms(2020, 3, 14, 9, 30, 0).unwrap();
+    assert!(same_time("Mar 14 2020", old.into()));
+    assert!(!same_time("Mar 15 2020", old.into()));
+  }
+}
@@ -0,0 +1,108 @@
+use crate::{announce, check, deploy, dry_run, lint, process, Config};
+use clap::ArgMatches;
+use colol::color;
+
+// Processes, checks, publishes and announces your site in one go
+// Each step only runs if everything before it worked, and we finish with what happened to each one
+// With --dry-run nothing is published, and there's nothing to announce either
+
+enum Outcome {
+  Done,
+  Failed,
+  // why it was skipped
+  Skipped(&'static str)
+}
+
+// returns whether the step worked, after showing why if it didn't
+fn step(name: &str, result: subprocess::Result<bool>) -> bool {
+  match result {
+    Ok(worked) => worked,
+    Err(err) => {
+      eprintln!("😭 '{}' failed: {}", name, err);
+      false
+    }
+  }
+}
+
+pub fn ship(config: &Config, matches: &ArgMatches) -> bool {
+  let send_toot = config.ship.toot || matches.is_present("toot");
+  let send_tweet = config.ship.tweet || matches.is_present("tweet");
+  // publish checks links itself if check_before_publish is on, so there's no need to do it twice
+  let steps = [
+    ("process", true),
+    ("lint", config.ship.lint),
+    ("check", config.ship.check && !config.check_before_publish),
+    ("publish", true),
+    ("announce", send_toot || send_tweet)
+  ];
+
+  let dry = matches.is_present("dry-run");
+  let mut outcomes = Vec::new();
+  let mut failed = false;
+  // false after a dry run, or if the server was already up to date
+  let mut published = true;
+  for (name, wanted) in steps.iter() {
+    if !wanted {
+      continue
+    }
+    if failed {
+      outcomes.push((*name, Outcome::Skipped("(skipped)")));
+      continue
+    }
+    if *name == "announce" && !published {
+      outcomes.push((*name, Outcome::Skipped(if dry { "(skipped, this is a dry run)" } else { "(skipped, nothing was published)" })));
+      continue
+    }
+    println!("🚢 {}", name);
+    let worked = match *name {
+      "process" => step(name, process(config, matches.is_present("verbose"), matches.is_present("force"))),
+      "lint" => step(name, lint::lint(config, false).map_err(Into::into)),
+      "check" => step(name, check::check(config).map_err(Into::into)),
+      "publish" if dry => {
+        published = false;
+        step(name, dry_run(config))
+      },
+      "publish" => match deploy(config) {
+        Ok(Some(deploy)) => {
+          published = !deploy.changes.map(|c| c.is_empty()).unwrap_or(false);
+          true
+        },
+        Ok(None) => false,
+        Err(err) => {
+          eprintln!("😭 '{}' failed: {}", name, err);
+          false
+        }
+      },
+      _ => announce(config, send_toot, send_tweet, matches.value_of("message"))
+    };
+    failed = !worked;
+    outcomes.push((*name, if worked { Outcome::Done } else { Outcome::Failed }));
+  }
+
+  println!();
+  for (name, outcome) in &outcomes {
+    let (symbol, note) = match outcome {
+      Outcome::Done => { color!(green); ("✔", "") },
+      Outcome::Failed => { color!(red); ("✘", "") },
+      Outcome::Skipped(why) => { color!(gray); ("-", *why) }
+    };
+    print!("  {}", symbol);
+    color!(reset);
+    if note.is_empty() {
+      println!(" {}", name);
+    } else {
+      println!(" {} {}", name, note);
+    }
+  }
+  let published = outcomes.iter().any(|(name, outcome)| *name == "publish" && matches!(outcome, Outcome::Done));
+  if !failed && dry {
+    println!("\nThis was a dry run, nothing was published");
+  } else if !failed {
+    println!("\nShipped! 🚀");
+  } else if published {
+    eprintln!("\nYour site is published, but announcing it didn't work");
+  } else {
+    eprintln!("\nNot shipped, fix the step that failed and try again");
+  }
+  !failed
+}
@@ -0,0 +1,100 @@
+use crate::{builtin, frontmatter, glob, url, Config};
+use chrono::SecondsFormat;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Makes sitemap.xml in output from the HTML pages there, for SSGs that don't make one themselves
+// Pages for posts get a lastmod from the date in the post's frontmatter, matched up by where
+// the post is published, i.e. posts/my-post.md is posts/my-post/index.html or posts/my-post.html
+
+// every HTML page under output, relative to it
+fn pages(dir: &Path, output: &Path, found: &mut Vec<String>) -> io::Result<()> {
+  for entry in fs::read_dir(dir)? {
+    let path = entry?.path();
+    if path.is_dir() {
+      pages(&path, output, found)?;
+    } else if path.extension().and_then(|e| e.to_str()) == Some("html") {
+      if let Ok(relative) = path.strip_prefix(output) {
+        found.push(relative.to_string_lossy().replace('\\', "/"));
+      }
+    }
+  }
+  Ok(())
+}
+
+// where a page is published, the same way as Post::url_path, e.g. posts/my-post for posts/my-post/index.html
+fn url_path(page: &str) -> String {
+  let path = page.trim_end_matches(".html").to_lowercase();
+  match path.strip_suffix("/index") {
+    Some(dir) => dir.to_string(),
+    None if path == "index" => String::new(),
+    None => path
+  }
+}
+
+// post dates by where they're published, skipping posts we can't read since lint is for finding those
+// posts are also matched under the name of the input directory, for SSGs that keep it in the URL, e.g. input = "content/posts"
+fn post_dates(config: &Config) -> HashMap<String, String> {
+  let input = PathBuf::from(shellexpand::full(&config.input).expect("Error reading input directory").as_ref());
+  let dir = input.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+  let mut dates = HashMap::new();
+  for post in frontmatter::markdown_files(&input).unwrap_or_default().iter().filter_map(|path| frontmatter::read(path).ok()) {
+    if let Some(date) = post.front.date() {
+      let date = date.to_rfc3339_opts(SecondsFormat::Secs, true);
+      let key = post.url_path(&input, &config.ssg_type);
+      if !dir.is_empty() {
+        dates.entry([dir.as_str(), "/", &key].concat()).or_insert_with(|| date.clone());
+      }
+      dates.entry(key).or_insert(date);
+    }
+  }
+  dates
+}
+
+// writes sitemap.xml and returns how many pages are in it
+pub fn build(config: &Config) -> io::Result<usize> {
+  if config.site_url.is_empty() {
+    return Err(io::Error::other("set site_url so lette.rs knows the URLs of your pages to make a sitemap"))
+  }
+  let output = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let mut found = Vec::new();
+  pages(&output, &output, &mut found)?;
+  found.retain(|page| !config.sitemap.exclude.iter().any(|pattern| glob::matches(pattern, page)));
+  found.sort();
+  let dates = post_dates(config);
+
+  let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n");
+  for page in &found {
+    xml.push_str("  <url>\n    <loc>");
+    xml.push_str(&builtin::escape(&url::page_url(&config.site_url, page)));
+    xml.push_str("</loc>\n");
+    if let Some(date) = dates.get(&url_path(page)) {
+      xml.push_str(&["    <lastmod>", date, "</lastmod>\n"].concat());
+    }
+    xml.push_str("  </url>\n");
+  }
+  xml.push_str("</urlset>\n");
+  fs::write(output.join("sitemap.xml"), xml)?;
+  Ok(found.len())
+}
+
+pub fn sitemap(config: &Config) -> io::Result<bool> {
+  let count = build(config)?;
+  println!("🗺  Wrote sitemap.xml with {} page{}", count, if count == 1 { "" } else { "s" });
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pages_are_matched_by_where_they_are_published() {
+    assert_eq!(url_path("posts/My-Post/index.html"), "posts/my-post");
+    assert_eq!(url_path("posts/my-post.html"), "posts/my-post");
+    assert_eq!(url_path("index.html"), "");
+    assert_eq!(url_path("about.html"), "about");
+  }
+}
@@ -0,0 +1,42 @@
+// URLs for the files in output, shared by everything that tells the outside world about them
+// (purging a CDN, pinging search engines, the sitemap) and by S3, which signs encoded paths
+
+// percent-encode everything except the characters AWS leaves alone, which is also safe for any URL path
+pub fn encode(s: &str, keep_slash: bool) -> String {
+  let mut encoded = String::new();
+  for b in s.bytes() {
+    match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(b as char),
+      b'/' if keep_slash => encoded.push('/'),
+      _ => encoded.push_str(&format!("%{:02X}", b))
+    }
+  }
+  encoded
+}
+
+// the URL a file in output is served at on site_url
+// posts/one/index.html -> posts/one/, so each page has one URL everywhere we use it
+pub fn page_url(site_url: &str, path: &str) -> String {
+  let path = encode(&path.replace('\\', "/"), true);
+  let path = if path == "index.html" || path.ends_with("/index.html") { path.trim_end_matches("index.html") } else { &path };
+  [site_url.trim_end_matches('/'), "/", path].concat()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn encodes_like_aws() {
+    assert_eq!(encode("posts/my post/ünï.html", true), "posts/my%20post/%C3%BCn%C3%AF.html");
+    assert_eq!(encode("a/b~c_d-e.f", false), "a%2Fb~c_d-e.f");
+    assert_eq!(encode("1+1=2", false), "1%2B1%3D2");
+  }
+
+  #[test]
+  fn page_urls_leave_out_index_html() {
+    assert_eq!(page_url("https://example.com/", "index.html"), "https://example.com/");
+    assert_eq!(page_url("https://example.com", "posts/one/index.html"), "https://example.com/posts/one/");
+    assert_eq!(page_url("https://example.com/blog", "my post.html"), "https://example.com/blog/my%20post.html");
+  }
+}
@@ -0,0 +1,80 @@
+use chrono::Local;
+use crate::{command, process, snapshot, source_paths, Config};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, SystemTime};
+
+// letters watch
+// Run the process command whenever something in input or extra_paths changes
+// This is for SSGs that don't have a watch mode, or where it doesn't work very well
+
+// how often to look for changes
+const POLL: Duration = Duration::from_millis(500);
+// how long files have to stay unchanged before we rebuild, so that saving lots of files at once only triggers one build
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+fn scan(paths: &[PathBuf], output: &Path) -> HashMap<PathBuf, SystemTime> {
+  let mut files = HashMap::new();
+  for path in paths {
+    snapshot(path, &mut files);
+  }
+  // changes to the output directory don't count, in case it lives inside input
+  files.retain(|path, _| !path.starts_with(output));
+  files
+}
+
+fn watch_hook(config: &Config, wd: &str) -> subprocess::Result<bool> {
+  Ok(command::build(&config.commands.watch_hook, &[], config)?.cwd(wd).join()?.success())
+}
+
+fn timestamp() -> String {
+  Local::now().format("%H:%M:%S").to_string()
+}
+
+pub fn watch(config: &Config, verbose: bool) -> subprocess::Result<()> {
+
+  let wd = shellexpand::full(&config.workdir).expect("Error reading working directory").to_string();
+  let output = PathBuf::from(shellexpand::full(&config.output).expect("Error reading output directory").as_ref());
+  let paths = source_paths(config);
+
+  // make sure we're up to date before we start
+  // the snapshot is always taken before processing, so anything saved during a build gets built next time round
+  let mut last = scan(&paths, &output);
+  process(config, verbose, false)?;
+  println!("👀 Watching for changes, press Ctrl-C to stop...");
+
+  loop {
+    sleep(POLL);
+    let mut current = scan(&paths, &output);
+    if current == last {
+      continue
+    }
+    // wait until things settle down
+    loop {
+      sleep(DEBOUNCE);
+      let again = scan(&paths, &output);
+      if again == current {
+        break
+      }
+      current = again;
+    }
+    let changed = current.iter().filter(|(path, modified)| last.get(*path) != Some(modified)).count()
+      + last.keys().filter(|path| !current.contains_key(*path)).count();
+    println!("[{}] {} file{} changed", timestamp(), changed, if changed == 1 { "" } else { "s" });
+    last = current;
+
+    // nothing in here stops us watching, we just say what went wrong and wait for the next change
+    match process(config, verbose, true) {
+      Ok(true) => if !config.commands.watch_hook.is_empty() {
+        match watch_hook(config, &wd) {
+          Ok(true) => (),
+          Ok(false) => eprintln!("😭 watch_hook failed: {}", config.commands.watch_hook),
+          Err(err) => eprintln!("😭 watch_hook couldn't run: {}", err)
+        }
+      },
+      Ok(false) => (),
+      Err(err) => eprintln!("😭 'process' command failed: {}", err)
+    }
+  }
+}